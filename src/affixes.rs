@@ -0,0 +1,196 @@
+use std::sync::OnceLock;
+
+use rand::{distributions::{IndependentSample, Weighted, WeightedChoice}, Rng};
+use crate::{
+    data,
+    combat::Resistances,
+    entity::Bundle,
+    items::Equipment,
+    rng::GameRng,
+    util::{Transition, from_dungeon_level},
+};
+
+/// Whether an affix goes before or after the item's name.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum Position {
+    Prefix,
+    Suffix,
+}
+
+/// What an affix can be rolled on. Weapons are anything with damage dice;
+/// everything else worn is armour.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum Gear {
+    Weapon,
+    Armour,
+}
+
+impl Gear {
+    pub fn of(equipment: &Equipment) -> Gear {
+        if equipment.damage.is_some() {
+            Gear::Weapon
+        } else {
+            Gear::Armour
+        }
+    }
+}
+
+/// What an affix adds on top of the item's own bonuses.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bonus {
+    pub power_bonus: i32,
+    pub defense_bonus: i32,
+    pub max_hp_bonus: i32,
+    pub movement_bonus: i32,
+    pub attacks_bonus: i32,
+    pub resistances: Resistances,
+}
+
+/// One prefix or suffix, as described in the affix data file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AffixDef {
+    pub id: String,
+    pub name: String,
+    pub position: Position,
+    /// Any kind of gear if not given.
+    pub applies_to: Option<Gear>,
+    pub bonus: Bonus,
+    pub spawn: Vec<Transition>,
+}
+
+impl AffixDef {
+    pub fn apply(&self, equipment: &mut Equipment) {
+        let bonus = &self.bonus;
+        equipment.power_bonus += bonus.power_bonus;
+        equipment.defense_bonus += bonus.defense_bonus;
+        equipment.max_hp_bonus += bonus.max_hp_bonus;
+        equipment.movement_bonus += bonus.movement_bonus;
+        equipment.attacks_bonus += bonus.attacks_bonus;
+        equipment.resistances += bonus.resistances;
+    }
+}
+
+static AFFIXES: OnceLock<Vec<AffixDef>> = OnceLock::new();
+
+pub fn init(path: &str) -> Result<(), String> {
+    let affixes = load(path)?;
+    AFFIXES.set(affixes).map_err(|_| "Affixes were already loaded".to_string())
+}
+
+pub fn all() -> &'static [AffixDef] {
+    AFFIXES.get().map_or(&[], |affixes| affixes.as_slice())
+}
+
+fn load(path: &str) -> Result<Vec<AffixDef>, String> {
+    let mut affixes: Vec<AffixDef> = vec![];
+
+    for mut entry in data::load_entries(path)? {
+        let affix = AffixDef {
+            id: entry.field("id")?,
+            name: entry.field("name")?,
+            position: entry.field("position")?,
+            applies_to: entry.optional_field("applies_to")?,
+            bonus: entry.field("bonus")?,
+            spawn: entry.optional_field("spawn")?.unwrap_or_default(),
+        };
+
+        if affixes.iter().any(|other| other.id == affix.id) {
+            return Err(format!("{}: field `id`: `{}` is used twice", entry.context(), affix.id));
+        }
+
+        entry.finish()?;
+        affixes.push(affix);
+    }
+
+    Ok(affixes)
+}
+
+pub fn affix_table_for_level(
+    position: Position,
+    gear: Gear,
+    level: u32,
+) -> Vec<Weighted<&'static AffixDef>> {
+    all()
+        .iter()
+        .filter(|affix| affix.position == position)
+        .filter(|affix| affix.applies_to.is_none() || affix.applies_to == Some(gear))
+        .map(|affix| Weighted {
+            weight: from_dungeon_level(&affix.spawn, level),
+            item: affix,
+        })
+        .filter(|entry| entry.weight > 0)
+        .collect()
+}
+
+/// Percent chance of a generated piece of equipment being enchanted.
+pub fn enchantment_chance(level: u32) -> u32 {
+    from_dungeon_level(
+        &[
+            Transition { level: 1, value: 10 },
+            Transition { level: 3, value: 25 },
+            Transition { level: 6, value: 40 },
+            Transition { level: 9, value: 60 },
+        ],
+        level,
+    )
+}
+
+/// The highest enchantment that can turn up on dungeon level `level`.
+pub fn max_enchantment(level: u32) -> i32 {
+    1 + level as i32 / 3
+}
+
+/// Percent chance of each of a prefix and a suffix.
+pub fn affix_chance(level: u32) -> u32 {
+    from_dungeon_level(
+        &[
+            Transition { level: 1, value: 5 },
+            Transition { level: 3, value: 15 },
+            Transition { level: 6, value: 30 },
+        ],
+        level,
+    )
+}
+
+/// Roll an enchantment and affixes onto a freshly made piece of equipment,
+/// naming it after them: "+2 keen sword of swiftness". Anything that isn't
+/// equipment is left alone.
+pub fn enchant(bundle: &mut Bundle, level: u32, rng: &mut GameRng) {
+    let equipment = match bundle.equipment {
+        Some(ref mut equipment) => equipment,
+        None => return,
+    };
+    let gear = Gear::of(equipment);
+    let mut name = bundle.object.name.clone();
+
+    for &position in &[Position::Prefix, Position::Suffix] {
+        let table = &mut affix_table_for_level(position, gear, level);
+
+        if !table.is_empty() && rng.gen_range(0, 100) < affix_chance(level) {
+            let affix = WeightedChoice::new(table).ind_sample(rng);
+            affix.apply(equipment);
+
+            name = match position {
+                Position::Prefix => format!("{} {}", affix.name, name),
+                Position::Suffix => format!("{} {}", name, affix.name),
+            };
+        }
+    }
+
+    if rng.gen_range(0, 100) < enchantment_chance(level) {
+        let enchantment = rng.gen_range(1, max_enchantment(level) + 1);
+
+        // Weapons and anything else that hits harder get sharper; the rest
+        // gets tougher.
+        if gear == Gear::Weapon || (equipment.power_bonus > 0 && equipment.defense_bonus == 0) {
+            equipment.power_bonus += enchantment;
+        } else {
+            equipment.defense_bonus += enchantment;
+        }
+
+        name = format!("+{} {}", enchantment, name);
+    }
+
+    bundle.object.name = name;
+}
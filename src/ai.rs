@@ -2,7 +2,10 @@ use crate::{
     types::*,
     consts::*,
     logging::*,
-    util::mut_two,
+    entity::{EntityId, ObjectStore},
+    status::{Status, has_status},
+    combat::attack,
+    faction::{are_hostile, is_ally},
     is_blocked,
 };
 use tcod::{
@@ -14,83 +17,186 @@ use rand::Rng;
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum Ai {
     Basic,
-    Confused {
-        previous_ai: Box<Ai>,
-        num_turns: i32,
-    },
+    /// A companion sticking close to the player.
+    Follow,
+    /// A companion told to hold its ground.
+    Stay,
 }
 
 pub fn ai_take_turn(
-    monster_id: usize,
+    monster_id: EntityId,
     game: &mut Game,
-    objects: &mut [Object],
+    objects: &mut ObjectStore,
     fov_map: &FovMap,
 ) {
     use Ai::*;
 
-    if let Some(ai) = objects[monster_id].ai.take() {
+    // Statuses take over before the monster gets to think.
+    if has_status(objects, monster_id, Status::Stunned) {
+        return;
+    }
+
+    if has_status(objects, monster_id, Status::Confused) {
+        ai_confused(monster_id, game, objects);
+        return;
+    }
+
+    if let Some(ai) = objects.ais.remove(monster_id) {
         let new_ai = match ai {
             Basic => ai_basic(monster_id, game, objects, fov_map),
-            Confused {
-                previous_ai,
-                num_turns,
-            } => ai_confused(monster_id, game, objects, previous_ai, num_turns),
+            Follow => ai_follow(monster_id, game, objects, fov_map),
+            Stay => ai_stay(monster_id, game, objects, fov_map),
         };
 
-        objects[monster_id].ai = Some(new_ai);
+        objects.ais.insert(monster_id, new_ai);
     }
 }
 
 pub fn ai_basic(
-    monster_id: usize,
+    monster_id: EntityId,
     game: &mut Game,
-    objects: &mut [Object],
+    objects: &mut ObjectStore,
     fov_map: &FovMap,
 ) -> Ai {
     let (monster_x, monster_y) = objects[monster_id].pos();
 
     if fov_map.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            attack(monster_id, PLAYER, objects, game);
+        if let Some(target_id) = closest_enemy(monster_id, objects, fov_map) {
+            if objects[monster_id].distance_to(&objects[target_id]) >= 2.0 {
+                let (target_x, target_y) = objects[target_id].pos();
+                move_towards(monster_id, target_x, target_y, &game.map, objects);
+            } else {
+                attack_repeatedly(monster_id, target_id, objects, game);
+            }
         }
     }
 
     Ai::Basic
 }
 
-pub fn ai_confused(
-    monster_id: usize,
+/// Go for anything hostile the player can see, or else catch up with the
+/// player. Companions are quicker on their feet than monsters and use all
+/// of their movement, otherwise they would be left behind every turn.
+pub fn ai_follow(
+    companion_id: EntityId,
     game: &mut Game,
-    objects: &mut [Object],
-    previous_ai: Box<Ai>,
-    num_turns: i32,
+    objects: &mut ObjectStore,
+    fov_map: &FovMap,
 ) -> Ai {
-    if num_turns >= 0 {
-        move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map,
-            objects,
-        );
-        Ai::Confused {
-            previous_ai: previous_ai,
-            num_turns: num_turns - 1,
+    let target_id = closest_enemy(companion_id, objects, fov_map);
+
+    for _ in 0..objects.movement(companion_id) {
+        let (target_x, target_y) = objects[target_id.unwrap_or(PLAYER)].pos();
+        if objects[companion_id].distance(target_x, target_y) < 2.0 {
+            break;
         }
+        move_towards(companion_id, target_x, target_y, &game.map, objects);
+    }
+
+    if let Some(target_id) = target_id {
+        if objects[companion_id].distance_to(&objects[target_id]) < 2.0 {
+            attack_repeatedly(companion_id, target_id, objects, game);
+        }
+    }
+
+    Ai::Follow
+}
+
+/// Hold still, but bite back at anything that comes close.
+pub fn ai_stay(
+    companion_id: EntityId,
+    game: &mut Game,
+    objects: &mut ObjectStore,
+    fov_map: &FovMap,
+) -> Ai {
+    if let Some(target_id) = closest_enemy(companion_id, objects, fov_map) {
+        if objects[companion_id].distance_to(&objects[target_id]) < 2.0 {
+            attack_repeatedly(companion_id, target_id, objects, game);
+        }
+    }
+
+    Ai::Stay
+}
+
+/// Use up every attack `attacker_id` has this turn on `target_id`, or stop
+/// once it is down.
+fn attack_repeatedly(attacker_id: EntityId, target_id: EntityId, objects: &mut ObjectStore, game: &mut Game) {
+    for swing in 0..objects.attacks(attacker_id) as usize {
+        if !objects[target_id].alive || !objects[attacker_id].alive {
+            break;
+        }
+        attack(attacker_id, target_id, swing, objects, game);
+    }
+}
+
+/// Tell every companion on the level to stay put, or, if they already
+/// are, to come along again.
+pub fn order_companions(objects: &mut ObjectStore, game: &mut Game) {
+    let companions: Vec<EntityId> = objects
+        .ais
+        .iter()
+        .map(|(id, _)| id)
+        .filter(|&id| is_ally(objects, id))
+        .collect();
+
+    if companions.is_empty() {
+        game.log.gutter_text("You have no companions to order about.", colors::WHITE);
+        return;
+    }
+
+    let following = companions
+        .iter()
+        .any(|&id| objects.ais.get(id) == Some(&Ai::Follow));
+    let (order, message) = if following {
+        (Ai::Stay, "You tell your companions to stay.")
     } else {
-        game.log.gutter_text(
-            format!("The {} is no longer confused.", objects[monster_id].name),
-            colors::RED,
-        );
+        (Ai::Follow, "You call your companions to follow you.")
+    };
+
+    for id in companions {
+        objects.ais.insert(id, order.clone());
+    }
+    game.log.gutter_text(message, colors::LIGHT_CYAN);
+}
+
+/// The nearest living thing in view that `id`'s faction wants dead.
+fn closest_enemy(id: EntityId, objects: &ObjectStore, fov_map: &FovMap) -> Option<EntityId> {
+    objects
+        .fighters
+        .iter()
+        .map(|(other, _)| other)
+        .filter(|&other| {
+            let object = &objects[other];
+            object.alive && fov_map.is_in_fov(object.x, object.y) && are_hostile(objects, id, other)
+        })
+        .min_by(|&a, &b| {
+            let a = objects[id].distance_to(&objects[a]);
+            let b = objects[id].distance_to(&objects[b]);
+            a.partial_cmp(&b).unwrap()
+        })
+}
+
+pub fn ai_confused(
+    monster_id: EntityId,
+    game: &mut Game,
+    objects: &mut ObjectStore,
+) {
+    let dx = game.rng.gen_range(-1, 2);
+    let dy = game.rng.gen_range(-1, 2);
+    let (x, y) = objects[monster_id].pos();
+
+    // It lashes out at whatever it stumbles into, friend or foe.
+    let bumped = objects
+        .fighters_at(x + dx, y + dy)
+        .find(|&id| id != monster_id);
 
-        *previous_ai
+    match bumped {
+        Some(target_id) => attack(monster_id, target_id, 0, objects, game),
+        None => move_by(monster_id, dx, dy, &game.map, objects),
     }
 }
 
-pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+pub fn move_towards(id: EntityId, target_x: i32, target_y: i32, map: &Map, objects: &mut ObjectStore) {
     let dx = target_x - objects[id].x;
     let dy = target_y - objects[id].y;
 
@@ -102,11 +208,14 @@ pub fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects:
     move_by(id, dx, dy, map, objects);
 }
 
-pub fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
+pub fn move_by(id: EntityId, dx: i32, dy: i32, map: &Map, objects: &mut ObjectStore) {
     let (x, y) = objects[id].pos();
     let blocked = is_blocked(x + dx, y + dy, map, objects);
-    if !blocked {
-        objects[id].x += dx;
-        objects[id].y += dy;
+
+    // Monsters know better than to walk into lava or off a ledge.
+    let hazard = id != PLAYER && map[(x + dx) as usize][(y + dy) as usize].kind.is_hazard();
+
+    if !blocked && !hazard {
+        objects.set_pos(id, x + dx, y + dy);
     }
 }
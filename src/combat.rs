@@ -0,0 +1,609 @@
+use std::fmt;
+
+use rand::Rng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use tcod::colors;
+
+use crate::{
+    consts::*,
+    types::*,
+    logging::*,
+    entity::{EntityId, ObjectStore},
+    status::{Status, apply_status},
+};
+
+/// A damage roll like `2d6+1`. Written that way in the data files and in
+/// saves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub fn new(count: i32, sides: i32) -> Self {
+        Dice {
+            count,
+            sides,
+            bonus: 0,
+        }
+    }
+
+    pub fn roll<R: Rng>(self, rng: &mut R) -> i32 {
+        let rolled: i32 = (0..self.count).map(|_| rng.gen_range(1, self.sides + 1)).sum();
+        rolled + self.bonus
+    }
+
+    pub fn min(self) -> i32 {
+        self.count + self.bonus
+    }
+
+    pub fn max(self) -> i32 {
+        self.count * self.sides + self.bonus
+    }
+
+    pub fn parse(text: &str) -> Result<Dice, String> {
+        let bad = || format!("`{}` is not a dice roll like `1d6` or `2d4+1`", text);
+
+        let (dice, bonus) = match text.find(['+', '-']) {
+            Some(at) => (&text[..at], text[at..].trim_start_matches('+').parse().map_err(|_| bad())?),
+            None => (text, 0),
+        };
+
+        let d = dice.find('d').ok_or_else(bad)?;
+        let count: i32 = dice[..d].parse().map_err(|_| bad())?;
+        let sides: i32 = dice[d + 1..].parse().map_err(|_| bad())?;
+
+        if count < 1 || sides < 1 {
+            return Err(bad());
+        }
+
+        Ok(Dice { count, sides, bonus })
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bonus {
+            0 => write!(f, "{}d{}", self.count, self.sides),
+            bonus if bonus > 0 => write!(f, "{}d{}+{}", self.count, self.sides, bonus),
+            bonus => write!(f, "{}d{}{}", self.count, self.sides, bonus),
+        }
+    }
+}
+
+impl Serialize for Dice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Dice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Dice::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Lightning,
+    Poison,
+    Cold,
+}
+
+impl fmt::Display for DamageType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DamageType::Physical => write!(f, "physical harm"),
+            DamageType::Fire => write!(f, "fire"),
+            DamageType::Lightning => write!(f, "lightning"),
+            DamageType::Poison => write!(f, "poison"),
+            DamageType::Cold => write!(f, "cold"),
+        }
+    }
+}
+
+impl DamageType {
+    /// What a spell or hazard of this kind does to you, as in "the lava
+    /// burns you".
+    pub fn verb(self) -> &'static str {
+        match self {
+            DamageType::Physical => "hits",
+            DamageType::Fire => "burns",
+            DamageType::Lightning => "shocks",
+            DamageType::Poison => "sickens",
+            DamageType::Cold => "freezes",
+        }
+    }
+}
+
+/// How much of each kind of damage is kept out, in percent. 100 or more is
+/// immunity, and below zero is a weakness: -100 takes double.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Resistances {
+    pub physical: i32,
+    pub fire: i32,
+    pub lightning: i32,
+    pub poison: i32,
+    pub cold: i32,
+}
+
+impl Resistances {
+    pub fn get(&self, kind: DamageType) -> i32 {
+        match kind {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Lightning => self.lightning,
+            DamageType::Poison => self.poison,
+            DamageType::Cold => self.cold,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Resistances {
+    fn add_assign(&mut self, other: Resistances) {
+        self.physical += other.physical;
+        self.fire += other.fire;
+        self.lightning += other.lightning;
+        self.poison += other.poison;
+        self.cold += other.cold;
+    }
+}
+
+/// What is left of `damage` after a `resistance` percent.
+pub fn resist(damage: i32, resistance: i32) -> i32 {
+    if resistance >= 100 {
+        0
+    } else {
+        damage * (100 - resistance) / 100
+    }
+}
+
+/// How did `kind` of damage go down with someone with `resistance` to it?
+/// Nothing to say if they are no better or worse off than anyone else.
+pub fn resistance_note(who: &str, is_player: bool, kind: DamageType, resistance: i32) -> Option<String> {
+    let note = match (resistance, is_player) {
+        (0, _) => return None,
+        (r, true) if r >= 100 => format!("You are immune to {}.", kind),
+        (r, false) if r >= 100 => format!("The {} is immune to {}.", who, kind),
+        (r, true) if r > 0 => format!("You resist the {}.", kind),
+        (r, false) if r > 0 => format!("The {} resists the {}.", who, kind),
+        (_, true) => format!("You are weak to {}!", kind),
+        (_, false) => format!("The {} is weak to {}!", who, kind),
+    };
+    Some(note)
+}
+
+/// How one swing went.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttackRoll {
+    Miss,
+    Hit { damage: i32, critical: bool },
+}
+
+/// Roll a d20 and add `power`; it hits on `TO_HIT_TARGET + defense` or more.
+/// A natural 20 always hits and rolls the damage dice twice, a natural 1
+/// always misses. Whatever gets through is cut down by the target's
+/// `damage_reduction`.
+pub fn roll_attack<R: Rng>(power: i32, defense: i32, dice: Dice, rng: &mut R) -> AttackRoll {
+    let roll = rng.gen_range(1, 21);
+
+    if !hits(roll, power, defense) {
+        return AttackRoll::Miss;
+    }
+
+    let critical = roll >= CRITICAL_ROLL;
+    let mut damage = dice.roll(rng);
+    if critical {
+        damage += dice.roll(rng) - dice.bonus;
+    }
+
+    AttackRoll::Hit {
+        damage: (damage + damage_bonus(power) - damage_reduction(defense)).max(0),
+        critical,
+    }
+}
+
+fn hits(roll: i32, power: i32, defense: i32) -> bool {
+    match roll {
+        1 => false,
+        roll if roll >= CRITICAL_ROLL => true,
+        roll => roll + power >= TO_HIT_TARGET + defense,
+    }
+}
+
+/// The chance out of 100 that an attacker with `power` hits `defense`.
+pub fn hit_chance(power: i32, defense: i32) -> i32 {
+    (1..21).filter(|&roll| hits(roll, power, defense)).count() as i32 * 5
+}
+
+pub fn damage_bonus(power: i32) -> i32 {
+    power / 2
+}
+
+pub fn damage_reduction(defense: i32) -> i32 {
+    (defense / 2).max(0)
+}
+
+/// The least and most an ordinary, non-critical hit can do.
+pub fn damage_range(dice: Dice, power: i32, defense: i32) -> (i32, i32) {
+    let adjust = damage_bonus(power) - damage_reduction(defense);
+    ((dice.min() + adjust).max(0), (dice.max() + adjust).max(0))
+}
+
+/// Who took part in a fight, with their name as it was at the time: by the
+/// time anyone reads the event a dead monster is already "remains of ...".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combatant {
+    pub id: EntityId,
+    pub name: String,
+}
+
+impl Combatant {
+    pub fn of(id: EntityId, objects: &ObjectStore) -> Self {
+        Combatant {
+            id,
+            name: objects[id].name.clone(),
+        }
+    }
+
+    pub fn is_player(&self) -> bool {
+        self.id == PLAYER
+    }
+
+    /// "you" or "the Orc", for the middle of a sentence.
+    pub fn describe(&self) -> String {
+        if self.is_player() {
+            "you".to_string()
+        } else {
+            format!("the {}", self.name)
+        }
+    }
+}
+
+/// Where some damage or healing came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Attack { attacker: Combatant, critical: bool },
+    /// An arrow or some such that `attacker` let fly.
+    Shot { attacker: Combatant, projectile: String, critical: bool },
+    /// A scroll or potion, and whoever used it.
+    Item { name: String, user: EntityId },
+    /// A status doing its work every turn, and whoever put it there.
+    Status { status: Status, applied_by: Option<EntityId> },
+    /// Lava and the like.
+    Terrain(String),
+}
+
+impl Source {
+    /// Who killed it, if this kills something.
+    fn credit(&self) -> Option<EntityId> {
+        match *self {
+            Source::Attack { ref attacker, .. } | Source::Shot { ref attacker, .. } => Some(attacker.id),
+            Source::Item { user, .. } => Some(user),
+            Source::Status { applied_by, .. } => applied_by,
+            Source::Terrain(_) => None,
+        }
+    }
+}
+
+/// Something that happened in a fight. Everything that hurts, heals or
+/// afflicts a creature is worked out by one of the `resolve_*` functions
+/// below, which say what happened as a list of these, and `publish` passes
+/// them on to whoever wants to know.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CombatEvent {
+    /// `projectile` is what was shot, for misses at range.
+    Miss {
+        attacker: Combatant,
+        target: Combatant,
+        projectile: Option<String>,
+    },
+    /// `damage` is what got through after `resistance`, and may be zero.
+    Hit {
+        source: Source,
+        target: Combatant,
+        damage: i32,
+        damage_type: DamageType,
+        resistance: i32,
+    },
+    Kill {
+        target: Combatant,
+        killer: Option<EntityId>,
+    },
+    XpGained {
+        id: EntityId,
+        xp: i32,
+    },
+    Healed {
+        source: Source,
+        target: Combatant,
+        amount: i32,
+    },
+    StatusApplied {
+        source: Source,
+        target: Combatant,
+        status: Status,
+        turns: i32,
+    },
+}
+
+/// Anything that wants to hear about fights.
+pub trait CombatListener {
+    fn on_event(&mut self, event: &CombatEvent);
+}
+
+/// Send `events` to every listener: the message log, and the running
+/// totals on the character screen.
+pub fn publish(events: Vec<CombatEvent>, game: &mut Game) {
+    for event in &events {
+        game.log.on_event(event);
+        game.stats.on_event(event);
+    }
+}
+
+/// `attacker` swings at `target` and everyone hears about it.
+/// `swing` counts the attacker's attacks so far this turn, which decides
+/// the weapon it strikes with.
+pub fn attack(attacker_id: EntityId, target_id: EntityId, swing: usize, objects: &mut ObjectStore, game: &mut Game) {
+    let events = resolve_attack(attacker_id, target_id, swing, objects, game);
+    publish(events, game);
+}
+
+pub fn resolve_attack(
+    attacker_id: EntityId,
+    target_id: EntityId,
+    swing: usize,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+) -> Vec<CombatEvent> {
+    let dice = objects.damage_dice(attacker_id, swing);
+    resolve_strike(attacker_id, target_id, dice, None, objects, game)
+}
+
+/// `attacker_id` shoots `projectile` at `target_id` for `dice` of damage.
+/// It rolls to hit the same way as a swing does.
+pub fn resolve_shot(
+    attacker_id: EntityId,
+    target_id: EntityId,
+    dice: Dice,
+    projectile: &str,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+) -> Vec<CombatEvent> {
+    resolve_strike(attacker_id, target_id, dice, Some(projectile.to_string()), objects, game)
+}
+
+fn resolve_strike(
+    attacker_id: EntityId,
+    target_id: EntityId,
+    dice: Dice,
+    projectile: Option<String>,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+) -> Vec<CombatEvent> {
+    let power = objects.power(attacker_id);
+    let defense = objects.defense(target_id);
+
+    let attacker = Combatant::of(attacker_id, objects);
+
+    match roll_attack(power, defense, dice, &mut game.rng) {
+        AttackRoll::Miss => vec![CombatEvent::Miss {
+            attacker,
+            target: Combatant::of(target_id, objects),
+            projectile,
+        }],
+        AttackRoll::Hit { damage, critical } => {
+            let source = match projectile {
+                Some(projectile) => Source::Shot { attacker, projectile, critical },
+                None => Source::Attack { attacker, critical },
+            };
+            resolve_damage(target_id, damage, DamageType::Physical, source, objects, game)
+        }
+    }
+}
+
+/// Deal `damage` to `target_id` through its resistances. If that kills it
+/// and the player did the killing, they get the experience; monsters that
+/// kill each other learn nothing from it.
+pub fn resolve_damage(
+    target_id: EntityId,
+    damage: i32,
+    damage_type: DamageType,
+    source: Source,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+) -> Vec<CombatEvent> {
+    let target = Combatant::of(target_id, objects);
+    let resistance = objects.resistance(target_id, damage_type);
+    let damage = resist(damage, resistance);
+    let killer = source.credit();
+
+    let mut events = vec![CombatEvent::Hit {
+        source,
+        target: target.clone(),
+        damage,
+        damage_type,
+        resistance,
+    }];
+
+    if let Some(xp) = objects.take_damage(target_id, damage, game) {
+        events.push(CombatEvent::Kill { target, killer });
+
+        if killer == Some(PLAYER) && target_id != PLAYER {
+            if let Some(fighter) = objects.fighters.get_mut(PLAYER) {
+                fighter.xp += xp;
+                events.push(CombatEvent::XpGained { id: PLAYER, xp });
+            }
+        }
+    }
+
+    events
+}
+
+pub fn resolve_heal(target_id: EntityId, amount: i32, source: Source, objects: &mut ObjectStore) -> Vec<CombatEvent> {
+    let before = objects.fighters.get(target_id).map_or(0, |fighter| fighter.hp);
+    objects.heal(target_id, amount);
+    let after = objects.fighters.get(target_id).map_or(0, |fighter| fighter.hp);
+
+    vec![CombatEvent::Healed {
+        source,
+        target: Combatant::of(target_id, objects),
+        amount: after - before,
+    }]
+}
+
+pub fn resolve_status(
+    target_id: EntityId,
+    status: Status,
+    turns: i32,
+    source: Source,
+    objects: &mut ObjectStore,
+) -> Vec<CombatEvent> {
+    apply_status(objects, target_id, status, turns, source.credit());
+
+    vec![CombatEvent::StatusApplied {
+        source,
+        target: Combatant::of(target_id, objects),
+        status,
+        turns,
+    }]
+}
+
+/// The message log's side of things.
+impl CombatListener for Messages {
+    fn on_event(&mut self, event: &CombatEvent) {
+        match *event {
+            CombatEvent::Miss { ref attacker, ref target, ref projectile } => {
+                let message = match *projectile {
+                    Some(ref projectile) => format!("{}'s {} misses {}.", attacker.name, projectile, target.name),
+                    None => format!("{} attacks {} and misses.", attacker.name, target.name),
+                };
+                self.gutter_text(message, colors::LIGHT_GREY);
+            }
+
+            CombatEvent::Hit { ref source, ref target, damage, damage_type, resistance } => {
+                if let Some(note) = resistance_note(&target.name, target.is_player(), damage_type, resistance) {
+                    self.gutter_text(note, colors::LIGHT_GREY);
+                }
+
+                let (message, color) = match *source {
+                    Source::Attack { ref attacker, .. } if damage == 0 => (
+                        format!("{} attacks {} but it has no effect!", attacker.name, target.name),
+                        colors::WHITE,
+                    ),
+                    Source::Attack { ref attacker, critical: true } => (
+                        format!("{} lands a critical hit on {} for {} hit points!", attacker.name, target.name, damage),
+                        colors::LIGHT_YELLOW,
+                    ),
+                    Source::Attack { ref attacker, critical: false } => (
+                        format!("{} attacks {} for {} hit points.", attacker.name, target.name, damage),
+                        colors::WHITE,
+                    ),
+                    Source::Shot { ref attacker, ref projectile, .. } if damage == 0 => (
+                        format!("{}'s {} hits {} but it has no effect!", attacker.name, projectile, target.name),
+                        colors::WHITE,
+                    ),
+                    Source::Shot { ref attacker, ref projectile, critical: true } => (
+                        format!("{}'s {} strikes {} in a weak spot for {} hit points!", attacker.name, projectile, target.name, damage),
+                        colors::LIGHT_YELLOW,
+                    ),
+                    Source::Shot { ref attacker, ref projectile, critical: false } => (
+                        format!("{}'s {} hits {} for {} hit points.", attacker.name, projectile, target.name, damage),
+                        colors::WHITE,
+                    ),
+                    Source::Item { ref name, .. } => (
+                        format!("The {} {} {} for {} hit points.", name, damage_type.verb(), target.describe(), damage),
+                        colors::ORANGE,
+                    ),
+                    Source::Status { status: Status::Poisoned, .. } => (
+                        format!("The poison burns {} for {} hit points.", target.describe(), damage),
+                        colors::DARK_GREEN,
+                    ),
+                    Source::Status { status, .. } => (
+                        format!("Being {} costs {} {} hit points.", status, target.describe(), damage),
+                        colors::DARK_GREEN,
+                    ),
+                    Source::Terrain(ref name) => (
+                        format!("The {} {} {} for {} hit points!", name, damage_type.verb(), target.describe(), damage),
+                        colors::ORANGE,
+                    ),
+                };
+                self.gutter_text(message, color);
+            }
+
+            CombatEvent::Kill { ref target, .. } => {
+                if target.is_player() {
+                    self.gutter_text("You died!", colors::RED);
+                } else {
+                    self.gutter_text(format!("{} is dead!", target.name), colors::ORANGE);
+                }
+            }
+
+            CombatEvent::XpGained { id, xp } => {
+                if id == PLAYER {
+                    self.gutter_text(format!("You gain {} experience points.", xp), colors::ORANGE);
+                }
+            }
+
+            CombatEvent::Healed { ref source, ref target, .. } => {
+                // Regeneration ticks away quietly every turn.
+                if let Source::Item { .. } = *source {
+                    if target.is_player() {
+                        self.gutter_text("Your wounds start to feel better!", colors::LIGHT_VIOLET);
+                    } else {
+                        self.gutter_text(format!("The {} looks healthier.", target.name), colors::LIGHT_VIOLET);
+                    }
+                }
+            }
+
+            CombatEvent::StatusApplied { ref target, status, .. } => {
+                let message = match status {
+                    _ if target.is_player() => format!("You are {}!", status),
+                    Status::Confused => format!(
+                        "The eyes of {} look vacant, as he starts to stumble around!",
+                        target.name
+                    ),
+                    _ => format!("The {} is {}!", target.name, status),
+                };
+                let color = if target.is_player() { colors::LIGHT_VIOLET } else { colors::LIGHT_GREEN };
+                self.gutter_text(message, color);
+            }
+        }
+    }
+}
+
+/// Running totals of how the player's fights have gone.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CombatStats {
+    pub hits: i32,
+    pub misses: i32,
+    pub kills: i32,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+}
+
+impl CombatListener for CombatStats {
+    fn on_event(&mut self, event: &CombatEvent) {
+        match *event {
+            CombatEvent::Miss { ref attacker, .. } if attacker.is_player() => self.misses += 1,
+            CombatEvent::Hit { ref target, damage, .. } if target.is_player() => self.damage_taken += damage,
+            CombatEvent::Hit { ref source, damage, .. } if source.credit() == Some(PLAYER) => {
+                // Poison the player left behind still counts as their
+                // damage, but each tick isn't a hit.
+                if !matches!(*source, Source::Status { .. }) {
+                    self.hits += 1;
+                }
+                self.damage_dealt += damage;
+            }
+            CombatEvent::Kill { killer: Some(PLAYER), ref target } if !target.is_player() => self.kills += 1,
+            _ => {}
+        }
+    }
+}
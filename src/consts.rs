@@ -20,21 +20,49 @@ pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 pub const ROOM_MAX_SIZE: i32 = 10;
 pub const ROOM_MIN_SIZE: i32 = 6;
 pub const MAX_ROOMS: i32 = 30;
+pub const BSP_MIN_LEAF_SIZE: i32 = 10;
 
-pub const PLAYER: usize = 0;
+pub const CAVE_INITIAL_WALL_CHANCE: f32 = 0.45;
+pub const CAVE_SMOOTHING_STEPS: i32 = 5;
+pub const CAVE_MIN_FLOOR_PERCENT: i32 = 40;
+pub const CAVE_SECTOR_SIZE: i32 = 8;
+
+pub const MONSTER_DATA: &str = "data/monsters.json";
+pub const ITEM_DATA: &str = "data/items.json";
+pub const AFFIX_DATA: &str = "data/affixes.json";
+pub const VAULT_DIR: &str = "vaults";
+pub const VAULT_CHANCE: f32 = 0.5;
+pub const VAULT_PLACEMENT_ATTEMPTS: i32 = 200;
+
+pub use crate::entity::PLAYER;
 
 pub const INVENTORY_WIDTH: i32 = 50;
 
-pub const HEAL_AMOUNT: i32 = 40;
+pub const SHALLOW_WATER_MOVEMENT_COST: i32 = 2;
+pub const LAVA_DAMAGE: i32 = 10;
+
+pub const WATER_POOLS_PER_LEVEL: i32 = 3;
+pub const WATER_POOL_SIZE: i32 = 25;
+pub const LAVA_POOL_SIZE: i32 = 15;
+pub const CHASM_SIZE: i32 = 8;
+pub const DOOR_CHANCE: f32 = 0.4;
+
+pub const THROW_RANGE: i32 = 6;
+pub const SPLASH_RADIUS: i32 = 1;
 
-pub const LIGHTNING_RANGE: i32 = 5;
-pub const LIGHTNING_DAMAGE: i32 = 40;
+pub const DUAL_WIELD_ATTACKS_BONUS: i32 = 1;
 
-pub const CONFUSE_NUM_TURNS: i32 = 10;
-pub const CONFUSE_RANGE: i32 = 8;
+pub const TO_HIT_TARGET: i32 = 10;
+pub const CRITICAL_ROLL: i32 = 20;
 
-pub const FIREBALL_RADIUS: i32 = 3;
-pub const FIREBALL_DAMAGE: i32 = 25;
+pub const POISON_DAMAGE: i32 = 2;
+pub const REGENERATION_AMOUNT: i32 = 3;
+pub const HASTE_MOVEMENT_BONUS: i32 = 2;
+pub const SLOW_MOVEMENT_PENALTY: i32 = 2;
+pub const HASTE_ATTACKS_BONUS: i32 = 1;
+pub const SLOW_ATTACKS_PENALTY: i32 = 1;
+pub const WEAKNESS_POWER_PENALTY: i32 = 2;
+pub const STUN_DEFENSE_PENALTY: i32 = 2;
 
 pub const LEVEL_UP_BASE: i32 = 200;
 pub const LEVEL_UP_FACTOR: i32 = 150;
@@ -43,6 +71,21 @@ pub const LEVEL_SCREEN_WIDTH: i32 = 40;
 
 pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
 
+pub const EQUIPMENT_SCREEN_WIDTH: i32 = 40;
+
+pub const END_SCREEN_WIDTH: i32 = 40;
+
+pub const FINAL_LEVEL: u32 = 10;
+pub const BOSS_MONSTER: &str = "ancient_king";
+pub const STARTING_COMPANION: &str = "dog";
+pub const GOAL_ITEM: &str = "crown";
+
+pub const COLOR_ALLY: Color = Color {
+    r: 0,
+    g: 63,
+    b: 127
+};
+
 pub const COLOR_DARK_WALL: Color = Color {
     r: 0,
     g: 0,
@@ -65,6 +108,50 @@ pub const COLOR_LIGHT_GROUND: Color = Color {
     b: 50,
 };
 
+pub const COLOR_DARK_DOOR: Color = Color {
+    r: 80,
+    g: 50,
+    b: 20,
+};
+pub const COLOR_LIGHT_DOOR: Color = Color {
+    r: 150,
+    g: 90,
+    b: 30,
+};
+
+pub const COLOR_DARK_WATER: Color = Color {
+    r: 20,
+    g: 40,
+    b: 120,
+};
+pub const COLOR_LIGHT_WATER: Color = Color {
+    r: 40,
+    g: 90,
+    b: 200,
+};
+
+pub const COLOR_DARK_LAVA: Color = Color {
+    r: 100,
+    g: 20,
+    b: 0,
+};
+pub const COLOR_LIGHT_LAVA: Color = Color {
+    r: 220,
+    g: 60,
+    b: 0,
+};
+
+pub const COLOR_DARK_CHASM: Color = Color {
+    r: 0,
+    g: 0,
+    b: 20,
+};
+pub const COLOR_LIGHT_CHASM: Color = Color {
+    r: 10,
+    g: 10,
+    b: 10,
+};
+
 pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 pub const FOV_LIGHT_WALLS: bool = true;
 pub const TORCH_RADIUS: i32 = 10;
@@ -0,0 +1,71 @@
+use std::fs;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// One object out of a JSON data file, pulled apart a field at a time so
+/// that a bad value can be reported with the file and field it came from.
+pub struct Entry {
+    context: String,
+    fields: Map<String, Value>,
+}
+
+impl Entry {
+    pub fn field<T: DeserializeOwned>(&mut self, name: &str) -> Result<T, String> {
+        match self.optional_field(name)? {
+            Some(value) => Ok(value),
+            None => Err(format!("{}: missing field `{}`", self.context, name)),
+        }
+    }
+
+    pub fn optional_field<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>, String> {
+        match self.fields.remove(name) {
+            Some(value) => serde_json::from_value(value)
+                .map(Some)
+                .map_err(|e| format!("{}: field `{}`: {}", self.context, name, e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn context(&self) -> &str {
+        &self.context
+    }
+
+    /// Call once every known field has been read, to catch typos.
+    pub fn finish(self) -> Result<(), String> {
+        match self.fields.keys().next() {
+            Some(name) => Err(format!("{}: unknown field `{}`", self.context, name)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Read `path` as a JSON array of objects. Each entry's context names the
+/// file, its position in the array and its `id`, if it has one.
+pub fn load_entries(path: &str) -> Result<Vec<Entry>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let json: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+
+    let array = match json {
+        Value::Array(array) => array,
+        _ => return Err(format!("{}: expected a list of entries", path)),
+    };
+
+    array
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let fields = match value {
+                Value::Object(fields) => fields,
+                _ => return Err(format!("{}: entry {}: expected an object", path, index)),
+            };
+
+            let context = match fields.get("id").and_then(Value::as_str) {
+                Some(id) => format!("{}: entry {} (`{}`)", path, index, id),
+                None => format!("{}: entry {}", path, index),
+            };
+
+            Ok(Entry { context, fields })
+        })
+        .collect()
+}
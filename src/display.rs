@@ -5,25 +5,23 @@ use tcod::{
 use crate::{
     consts::*,
     types::*,
+    entity::{Bundle, ObjectStore},
+    faction::is_ally,
+    items,
     inputs,
 };
 
 pub fn render_all(
     tcod: &mut Tcod,
-    objects: &[Object],
+    objects: &ObjectStore,
     game: &mut Game,
 ) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
             let visible = tcod.fov.is_in_fov(x, y);
-            let wall = game.map[x as usize][y as usize].block_sight;
+            let kind = game.map[x as usize][y as usize].kind;
 
-            let color = match (visible, wall) {
-                (false, true) => COLOR_DARK_WALL,
-                (false, false) => COLOR_DARK_GROUND,
-                (true, true) => COLOR_LIGHT_WALL,
-                (true, false) => COLOR_LIGHT_GROUND,
-            };
+            let (color, glyph_color) = kind.colors(visible);
 
             let explored = &mut game.map[x as usize][y as usize].explored;
 
@@ -32,21 +30,28 @@ pub fn render_all(
             }
 
             if *explored {
-                tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
+                match kind.glyph() {
+                    Some(glyph) => tcod.con.put_char_ex(x, y, glyph, glyph_color, color),
+                    None => tcod.con.set_char_background(x, y, color, BackgroundFlag::Set),
+                }
             }
         }
     }
 
     let mut to_draw: Vec<_> = objects.
         iter().
-        filter(|o| tcod.fov.is_in_fov(o.x, o.y) ||
+        filter(|(_, o)| tcod.fov.is_in_fov(o.x, o.y) ||
                (o.always_visible && game.map[o.x as usize][o.y as usize].explored)).
         collect();
 
-    to_draw.sort_by(|o1, o2| { o1.blocks.cmp(&o2.blocks) });
+    to_draw.sort_by(|(_, o1), (_, o2)| { o1.blocks.cmp(&o2.blocks) });
 
-    for object in &to_draw {
+    for &(id, object) in &to_draw {
         object.draw(&mut tcod.con);
+
+        if is_ally(objects, id) {
+            tcod.con.set_char_background(object.x, object.y, COLOR_ALLY, BackgroundFlag::Set);
+        }
     }
 
     blit(
@@ -62,8 +67,8 @@ pub fn render_all(
     tcod.panel.set_default_background(colors::BLACK);
     tcod.panel.clear();
 
-    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
-    let max_hp = objects[PLAYER].max_hp(game);
+    let hp = objects.fighters.get(PLAYER).map_or(0, |f| f.hp);
+    let max_hp = objects.max_hp(PLAYER);
 
     render_bar(
         &mut tcod.panel,
@@ -85,6 +90,17 @@ pub fn render_all(
         format!("Dungeon level: {}", game.dungeon_level),
     );
 
+    let effects = objects.statuses.get(PLAYER).map_or(&[][..], |effects| effects.as_slice());
+    for (y, effect) in (5..PANEL_HEIGHT).zip(effects) {
+        tcod.panel.print_ex(
+            1,
+            y,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{} ({})", effect.status, effect.turns),
+        );
+    }
+
     tcod.panel.set_default_foreground(colors::LIGHT_GREY);
     tcod.panel.print_ex(
         1,
@@ -215,7 +231,7 @@ pub fn menu<T: AsRef<str>>(
 }
 
 pub fn inventory_menu(
-    inventory: &[Object],
+    inventory: &[Bundle],
     header: &str,
     root: &mut Root,
 ) -> Option<usize> {
@@ -227,9 +243,9 @@ pub fn inventory_menu(
             .map(|item| {
                 match item.equipment {
                     Some(equipment) if equipment.equipped => {
-                        format!("{} (on {})", item.name, equipment.slot)
+                        format!("{} (on {})", items::item_name(item), equipment.worn_on())
                     },
-                    _ => item.name.clone(),
+                    _ => items::item_name(item),
                 }
             }).collect()
     };
@@ -247,3 +263,46 @@ pub fn msgbox(text: &str, width: i32, root: &mut Root) {
     let options: &[&str] = &[];
     menu(text, options, width, root);
 }
+
+pub fn number_prompt(header: &str, width: i32, root: &mut Root) -> Option<u64> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape};
+
+    let mut digits = String::new();
+
+    loop {
+        let text = format!("{}{}_", header, digits);
+        let height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, &text);
+
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            &text,
+        );
+
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        tcod::console::blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+
+        match key.code {
+            Enter => return digits.parse().ok(),
+            Escape => return None,
+            Backspace => {
+                digits.pop();
+            }
+            _ if key.printable.is_ascii_digit() && digits.len() < 19 => {
+                digits.push(key.printable);
+            }
+            _ => {}
+        }
+    }
+}
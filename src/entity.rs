@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+
+use crate::{
+    types::{Fighter, Object},
+    ai::Ai,
+    items::{Equipment, Item},
+    monsters::Monster,
+    status::StatusEffects,
+};
+
+/// A handle to an object on the current level.
+///
+/// Removing an object bumps the generation of its slot, so an old id can
+/// never be mistaken for whatever is put in the slot next. Ids are saved as
+/// they are, so they stay valid across a save and load.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+/// The player always lives in the first slot of every level's store.
+pub const PLAYER: EntityId = EntityId {
+    index: 0,
+    generation: 0,
+};
+
+/// One component table, indexed like the store's slots. Each entry keeps
+/// the generation of the entity it belongs to, so a stale id finds nothing.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Components<T> {
+    entries: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Components<T> {
+    pub fn new() -> Self {
+        Components { entries: vec![] }
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        match self.entries.get(id.index as usize) {
+            Some(Some((generation, component))) if *generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        match self.entries.get_mut(id.index as usize) {
+            Some(Some((generation, component))) if *generation == id.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Give `id` this component, replacing any it already had. Only call
+    /// this for an id that is still in the store.
+    pub fn insert(&mut self, id: EntityId, component: T) {
+        let index = id.index as usize;
+        if self.entries.len() <= index {
+            self.entries.resize_with(index + 1, || None);
+        }
+
+        self.entries[index] = Some((id.generation, component));
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        let entry = self.entries.get_mut(id.index as usize)?;
+
+        match entry {
+            Some((generation, _)) if *generation == id.generation => entry.take().map(|(_, component)| component),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.entries.iter().enumerate().filter_map(|(index, entry)| {
+            entry.as_ref().map(|(generation, component)| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation: *generation,
+                    },
+                    component,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
+        self.entries.iter_mut().enumerate().filter_map(|(index, entry)| {
+            entry.as_mut().map(|(generation, component)| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation: *generation,
+                    },
+                    component,
+                )
+            })
+        })
+    }
+}
+
+impl<T> Default for Components<T> {
+    fn default() -> Self {
+        Components::new()
+    }
+}
+
+/// What a creature is carrying, equipped or not.
+pub type Inventory = Vec<Bundle>;
+
+/// An entity that isn't in any store: the player between levels, or an
+/// item in a backpack. Adding a component means adding a field here, a
+/// table to `ObjectStore` and `SavedStore`, and a line each to `attach` and
+/// `detach`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Bundle {
+    pub object: Object,
+    pub fighter: Option<Fighter>,
+    pub ai: Option<Ai>,
+    pub monster: Option<Monster>,
+    pub statuses: Option<StatusEffects>,
+    pub item: Option<Item>,
+    pub equipment: Option<Equipment>,
+    pub inventory: Option<Inventory>,
+}
+
+impl From<Object> for Bundle {
+    fn from(object: Object) -> Self {
+        Bundle {
+            object,
+            fighter: None,
+            ai: None,
+            monster: None,
+            statuses: None,
+            item: None,
+            equipment: None,
+            inventory: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Slot {
+    generation: u32,
+    object: Option<Object>,
+}
+
+/// Every entity on a level, keyed by `EntityId`.
+///
+/// Every entity has an `Object`, which is its name, position and glyph, and
+/// `objects[id]` gets at it directly. Everything else lives in a component
+/// table and an entity only has the components it needs.
+///
+/// The store also keeps track of what is on each tile, so always move
+/// things with `set_pos` rather than writing to `x` and `y`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(from = "SavedStore")]
+pub struct ObjectStore {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+
+    pub fighters: Components<Fighter>,
+    pub ais: Components<Ai>,
+    pub monsters: Components<Monster>,
+    pub statuses: Components<StatusEffects>,
+    pub items: Components<Item>,
+    pub equipment: Components<Equipment>,
+    pub inventories: Components<Inventory>,
+
+    #[serde(skip_serializing)]
+    occupancy: HashMap<(i32, i32), Vec<EntityId>>,
+}
+
+/// The saved form of an `ObjectStore`. The occupancy index isn't saved but
+/// rebuilt from the positions on load.
+#[derive(Deserialize)]
+struct SavedStore {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    fighters: Components<Fighter>,
+    ais: Components<Ai>,
+    monsters: Components<Monster>,
+    statuses: Components<StatusEffects>,
+    items: Components<Item>,
+    equipment: Components<Equipment>,
+    inventories: Components<Inventory>,
+}
+
+impl From<SavedStore> for ObjectStore {
+    fn from(saved: SavedStore) -> Self {
+        let mut store = ObjectStore {
+            slots: saved.slots,
+            free: saved.free,
+            fighters: saved.fighters,
+            ais: saved.ais,
+            monsters: saved.monsters,
+            statuses: saved.statuses,
+            items: saved.items,
+            equipment: saved.equipment,
+            inventories: saved.inventories,
+            occupancy: HashMap::new(),
+        };
+
+        let positions: Vec<_> = store.iter().map(|(id, object)| (id, object.pos())).collect();
+        for (id, tile) in positions {
+            store.occupancy.entry(tile).or_default().push(id);
+        }
+
+        store
+    }
+}
+
+impl ObjectStore {
+    /// An empty store, with the player's slot set aside.
+    pub fn new() -> Self {
+        ObjectStore {
+            slots: vec![Slot {
+                generation: PLAYER.generation,
+                object: None,
+            }],
+            free: vec![],
+            fighters: Components::new(),
+            ais: Components::new(),
+            monsters: Components::new(),
+            statuses: Components::new(),
+            items: Components::new(),
+            equipment: Components::new(),
+            inventories: Components::new(),
+            occupancy: HashMap::new(),
+        }
+    }
+
+    pub fn with_player(player: Bundle) -> Self {
+        let mut store = ObjectStore::new();
+        store.put_player(player);
+        store
+    }
+
+    pub fn insert<B: Into<Bundle>>(&mut self, bundle: B) -> EntityId {
+        let id = match self.free.pop() {
+            Some(index) => EntityId {
+                index,
+                generation: self.slots[index as usize].generation,
+            },
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    object: None,
+                });
+
+                EntityId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        self.attach(id, bundle.into());
+        id
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Bundle> {
+        assert!(id != PLAYER, "The player can't be removed, use take_player.");
+
+        let bundle = self.detach(id)?;
+
+        let slot = &mut self.slots[id.index as usize];
+        slot.generation += 1;
+        self.free.push(id.index);
+
+        Some(bundle)
+    }
+
+    /// Lift the player out, to carry them to another level.
+    pub fn take_player(&mut self) -> Bundle {
+        self.detach(PLAYER).expect("There is no player on this level.")
+    }
+
+    pub fn put_player(&mut self, player: Bundle) {
+        self.attach(PLAYER, player);
+    }
+
+    fn attach(&mut self, id: EntityId, bundle: Bundle) {
+        self.occupancy.entry(bundle.object.pos()).or_default().push(id);
+        self.slots[id.index as usize].object = Some(bundle.object);
+
+        if let Some(fighter) = bundle.fighter {
+            self.fighters.insert(id, fighter);
+        }
+        if let Some(ai) = bundle.ai {
+            self.ais.insert(id, ai);
+        }
+        if let Some(monster) = bundle.monster {
+            self.monsters.insert(id, monster);
+        }
+        if let Some(statuses) = bundle.statuses {
+            self.statuses.insert(id, statuses);
+        }
+        if let Some(item) = bundle.item {
+            self.items.insert(id, item);
+        }
+        if let Some(equipment) = bundle.equipment {
+            self.equipment.insert(id, equipment);
+        }
+        if let Some(inventory) = bundle.inventory {
+            self.inventories.insert(id, inventory);
+        }
+    }
+
+    fn detach(&mut self, id: EntityId) -> Option<Bundle> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        let object = slot.object.take()?;
+        self.unoccupy(object.pos(), id);
+
+        Some(Bundle {
+            object,
+            fighter: self.fighters.remove(id),
+            ai: self.ais.remove(id),
+            monster: self.monsters.remove(id),
+            statuses: self.statuses.remove(id),
+            item: self.items.remove(id),
+            equipment: self.equipment.remove(id),
+            inventory: self.inventories.remove(id),
+        })
+    }
+
+    fn unoccupy(&mut self, tile: (i32, i32), id: EntityId) {
+        if let Some(ids) = self.occupancy.get_mut(&tile) {
+            ids.retain(|&other| other != id);
+            if ids.is_empty() {
+                self.occupancy.remove(&tile);
+            }
+        }
+    }
+
+    pub fn set_pos(&mut self, id: EntityId, x: i32, y: i32) {
+        let old_tile = self[id].pos();
+        if old_tile == (x, y) {
+            return;
+        }
+
+        self.unoccupy(old_tile, id);
+        self.occupancy.entry((x, y)).or_default().push(id);
+
+        let object = &mut self[id];
+        object.x = x;
+        object.y = y;
+    }
+
+    /// Everything on `(x, y)`, in the order it arrived there.
+    pub fn entities_at(&self, x: i32, y: i32) -> impl Iterator<Item = EntityId> + '_ {
+        self.occupancy.get(&(x, y)).into_iter().flatten().copied()
+    }
+
+    /// Everything on `(x, y)` that has a component in `table`, for example
+    /// `objects.at_with(x, y, &objects.items)`.
+    pub fn at_with<'a, T>(&'a self, x: i32, y: i32, table: &'a Components<T>) -> impl Iterator<Item = EntityId> + 'a {
+        self.entities_at(x, y).filter(move |&id| table.contains(id))
+    }
+
+    /// Every creature on `(x, y)`, dead or alive.
+    pub fn fighters_at(&self, x: i32, y: i32) -> impl Iterator<Item = EntityId> + '_ {
+        self.at_with(x, y, &self.fighters)
+    }
+
+    /// Whatever is standing in the way on `(x, y)`, if anything.
+    pub fn blocker_at(&self, x: i32, y: i32) -> Option<EntityId> {
+        self.entities_at(x, y).find(|&id| self[id].blocks)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Object> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.object.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Object> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.object.as_mut())
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Mutably borrow two *separate* objects. Panics if the ids are the same
+    /// or either one is stale.
+    pub fn pair_mut(&mut self, first: EntityId, second: EntityId) -> (&mut Object, &mut Object) {
+        assert!(first != second);
+        assert!(self.contains(first) && self.contains(second));
+
+        let split_at = std::cmp::max(first.index, second.index) as usize;
+        let (low, high) = self.slots.split_at_mut(split_at);
+
+        let (first_slot, second_slot) = if first.index < second.index {
+            (&mut low[first.index as usize], &mut high[0])
+        } else {
+            (&mut high[0], &mut low[second.index as usize])
+        };
+
+        (
+            first_slot.object.as_mut().unwrap(),
+            second_slot.object.as_mut().unwrap(),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Object)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.object.as_ref().map(|object| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    object,
+                )
+            })
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (EntityId, &mut Object)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let generation = slot.generation;
+            slot.object.as_mut().map(|object| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation,
+                    },
+                    object,
+                )
+            })
+        })
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.iter().map(|(_, object)| object)
+    }
+
+    /// A snapshot of the ids currently in the store, for loops that add or
+    /// remove objects as they go.
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+
+    /// The first object matching `predicate`.
+    pub fn find<P>(&self, mut predicate: P) -> Option<EntityId>
+    where
+        P: FnMut(&Object) -> bool,
+    {
+        self.iter()
+            .find(|&(_, object)| predicate(object))
+            .map(|(id, _)| id)
+    }
+}
+
+impl Index<EntityId> for ObjectStore {
+    type Output = Object;
+
+    fn index(&self, id: EntityId) -> &Object {
+        self.get(id)
+            .unwrap_or_else(|| panic!("{:?} is not on this level.", id))
+    }
+}
+
+impl IndexMut<EntityId> for ObjectStore {
+    fn index_mut(&mut self, id: EntityId) -> &mut Object {
+        self.get_mut(id)
+            .unwrap_or_else(|| panic!("{:?} is not on this level.", id))
+    }
+}
+
+impl Default for ObjectStore {
+    fn default() -> Self {
+        ObjectStore::new()
+    }
+}
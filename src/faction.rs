@@ -0,0 +1,41 @@
+use crate::{
+    consts::*,
+    entity::{EntityId, ObjectStore},
+};
+
+/// Which side a creature is on. Everything is out to get the player, but
+/// monsters only go for each other when their factions have a feud.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Faction {
+    Player,
+    Orcs,
+    Trolls,
+    Undead,
+}
+
+impl Faction {
+    pub fn is_hostile_to(self, other: Faction) -> bool {
+        use Faction::*;
+
+        match (self, other) {
+            (a, b) if a == b => false,
+            (Player, _) | (_, Player) => true,
+            (Orcs, Undead) | (Undead, Orcs) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Is `id` on the player's side, other than the player themselves?
+pub fn is_ally(objects: &ObjectStore, id: EntityId) -> bool {
+    id != PLAYER && objects.fighters.get(id).is_some_and(|fighter| fighter.faction == Faction::Player)
+}
+
+/// Would `a` attack `b` if it could? Anything without a fighter is fair
+/// game for nobody.
+pub fn are_hostile(objects: &ObjectStore, a: EntityId, b: EntityId) -> bool {
+    match (objects.fighters.get(a), objects.fighters.get(b)) {
+        (Some(a), Some(b)) => a.faction.is_hostile_to(b.faction),
+        _ => false,
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
     consts::*,
     types::*,
+    entity::ObjectStore,
     logging::*,
+    combat,
+    ai::order_companions,
     PlayerAction::{self, *},
     display::*,
     items::*,
     next_level,
+    previous_level,
     player_move_or_attack,
+    set_tile_kind,
 };
 
 use tcod::{
@@ -18,7 +23,7 @@ use tcod::{
 pub fn handle_keys(
     key: Key,
     tcod: &mut Tcod,
-    objects: &mut Vec<Object>,
+    objects: &mut ObjectStore,
     game: &mut Game,
 ) -> PlayerAction {
 
@@ -44,9 +49,10 @@ pub fn handle_keys(
 
         // Pressed '<'
         (Key { printable: ',', shift: true, .. }, true) => {
+            let (player_x, player_y) = objects[PLAYER].pos();
             let player_on_stairs = objects
-                .iter()
-                .any(|object| object.pos() == objects[PLAYER].pos() && object.name == "stairs");
+                .entities_at(player_x, player_y)
+                .any(|id| objects[id].name == "down stairs");
 
             if player_on_stairs {
                 next_level(tcod, objects, game);
@@ -60,8 +66,39 @@ pub fn handle_keys(
             DidntTakeTurn
         }
 
+        // Pressed '>'
+        (Key { printable: '.', shift: true, .. }, true) => {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            let player_on_stairs = objects
+                .entities_at(player_x, player_y)
+                .any(|id| objects[id].name == "up stairs");
+
+            let has_goal = inventory_of(objects, PLAYER)
+                .iter()
+                .any(|item| item.item.as_ref().map(|i| i.id.as_str()) == Some(GOAL_ITEM));
+
+            if !player_on_stairs {
+                game.log.gutter_text(
+                    "You cannot go up from here",
+                    colors::WHITE,
+                );
+            } else if game.dungeon_level > 1 {
+                previous_level(tcod, objects, game);
+            } else if has_goal {
+                return Victory;
+            } else {
+                game.log.gutter_text(
+                    "You cannot leave without the crown of the Ancient Kings.",
+                    colors::WHITE,
+                );
+            }
+
+            DidntTakeTurn
+        }
+
         (Key { code, printable, .. }, true) => match (code, printable) {
             (Char, '.') => EndedMove,
+            (Char, 'f') => Fire,
 
             (Char, 'k') | (Up, _) => Move(0, -1),
             (Char, 'j') | (Down, _) => Move(0, 1),
@@ -78,14 +115,13 @@ pub fn handle_keys(
                     colors::WHITE,
                 );
 
-                let item_id = objects
-                    .iter()
-                    .position(|object| object.pos() == objects[PLAYER].pos() && object.item.is_some());
+                let (player_x, player_y) = objects[PLAYER].pos();
+                let item_id = objects.at_with(player_x, player_y, &objects.items).next();
 
                 println!("Item_id: {:?}", item_id);
 
                 if let Some(item_id) = item_id {
-                    pick_item_up(item_id as usize, objects, game);
+                    pick_item_up(item_id, objects, game);
                 }
 
                 DidntTakeTurn
@@ -93,7 +129,7 @@ pub fn handle_keys(
 
             (Char, 'd') => {
                 let inventory_index = inventory_menu(
-                    &mut game.inventory,
+                    inventory_of(objects, PLAYER),
                     "Select the ittem to drop.\n",
                     &mut tcod.root,
                 );
@@ -107,7 +143,7 @@ pub fn handle_keys(
 
             (Char, 'i') => {
                 let inventory_index = inventory_menu(
-                    &game.inventory,
+                    inventory_of(objects, PLAYER),
                     "Press they key next to an item to use it, or any other to cancel.\n",
                     &mut tcod.root,
                 );
@@ -117,12 +153,71 @@ pub fn handle_keys(
                 DidntTakeTurn
             },
 
+            (Char, 't') => Throw,
+
+            (Char, 'x') => {
+                let (player_x, player_y) = objects[PLAYER].pos();
+
+                let door = (-1..2)
+                    .flat_map(|dx| (-1..2).map(move |dy| (player_x + dx, player_y + dy)))
+                    .find(|&(x, y)| {
+                        game.map[x as usize][y as usize].kind == TileKind::OpenDoor
+                            && objects.entities_at(x, y).next().is_none()
+                    });
+
+                match door {
+                    Some((x, y)) => {
+                        set_tile_kind(x, y, TileKind::ClosedDoor, game, tcod);
+                        tcod.fov.compute_fov(player_x, player_y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+                        game.log.gutter_text("You close the door.", colors::WHITE);
+                    }
+                    None => {
+                        game.log.gutter_text("There is no open door to close.", colors::WHITE);
+                    }
+                }
+
+                DidntTakeTurn
+            }
+
+            (Char, 'o') => {
+                order_companions(objects, game);
+                DidntTakeTurn
+            }
+
+            (Char, 'e') => {
+                let inventory = inventory_of(objects, PLAYER);
+                let slots: Vec<String> = Slot::ALL
+                    .iter()
+                    .map(|&slot| {
+                        let worn = get_equipped_in_slot(slot, inventory)
+                            .map_or("-".to_string(), |index| item_name(&inventory[index]));
+                        format!("{:<18} {}", slot.to_string(), worn)
+                    })
+                    .collect();
+
+                let msg = format!("Equipment\n\n{}", slots.join("\n"));
+                msgbox(&msg, EQUIPMENT_SCREEN_WIDTH, &mut tcod.root);
+
+                DidntTakeTurn
+            }
+
             (Char, 'c') => {
                 let player = &objects[PLAYER];
                 let level = player.level;
                 let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
 
-                if let Some(fighter) = player.fighter.as_ref() {
+                if let Some(fighter) = objects.fighters.get(PLAYER) {
+                    let power = objects.power(PLAYER);
+                    let defense = objects.defense(PLAYER);
+                    let dice = objects.damage_dice(PLAYER, 0);
+                    let (min_damage, max_damage) = combat::damage_range(dice, power, 0);
+                    let weapons = objects
+                        .weapon_dice(PLAYER)
+                        .iter()
+                        .map(|dice| dice.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" then ");
+
                     let msg = format!(
                         "Character information
 
@@ -131,14 +226,31 @@ Experience: {}
 Experience to level up: {}
 
 Maximum HP: {}
-Attack: {}
-Defence: {}",
+Attack: {} ({}% to hit, {}-{} damage with {})
+Defence: {} (blocks {} damage)
+
+Kills: {}
+Hits: {} Misses: {}
+Damage dealt: {} Damage taken: {}
+
+Seed: {}",
                         level,
                         fighter.xp,
                         level_up_xp,
-                        player.max_hp(game),
-                        player.power(game),
-                        player.defense(game)
+                        objects.max_hp(PLAYER),
+                        power,
+                        combat::hit_chance(power, 0),
+                        min_damage,
+                        max_damage,
+                        weapons,
+                        defense,
+                        combat::damage_reduction(defense),
+                        game.stats.kills,
+                        game.stats.hits,
+                        game.stats.misses,
+                        game.stats.damage_dealt,
+                        game.stats.damage_taken,
+                        game.seed
                     );
 
                     msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
@@ -162,15 +274,19 @@ Defence: {}",
 
 pub fn get_names_under_mouse(
     mouse: Mouse,
-    objects: &[Object],
+    objects: &ObjectStore,
     fov_map: &FovMap,
 ) -> String {
     let (x, y) = (mouse.cx as i32, mouse.cy as i32);
 
+    let on_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+    if !on_map || !fov_map.is_in_fov(x, y) {
+        return String::new();
+    }
+
     let names = objects
-        .iter()
-        .filter(|obj| { obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y) })
-        .map(|obj| obj.name.clone())
+        .entities_at(x, y)
+        .map(|id| objects[id].name.clone())
         .collect::<Vec<_>>();
 
     names.join(", ")
@@ -2,186 +2,263 @@ use crate::{
     consts::*,
     types::*,
     logging::*,
-    ai::Ai,
+    data,
+    affixes,
+    rng::GameRng,
+    status::Status,
+    combat::{self, CombatEvent, DamageType, Dice, Resistances, Source},
+    faction::is_ally,
+    entity::{Bundle, EntityId, Inventory, ObjectStore},
     util::{Transition, from_dungeon_level},
 
     target_monster,
     closest_monster,
     target_tile,
+    projectile_path,
+    scatter,
 };
+use std::sync::OnceLock;
+
 use tcod::{
-    colors,
+    colors::{self, Color},
 };
 use rand::{distributions::{IndependentSample, Weighted, WeightedChoice}, Rng};
 
+/// What an item object is. Only the id is stored on the object (and in
+/// saves); everything else lives in the item's `ItemDef`, apart from the
+/// name and equipment bonuses, which are rolled for each item and kept on
+/// the object and its `Equipment`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Item {
+    pub id: String,
+    /// How many are in the pile. Only ammunition comes in piles of more
+    /// than one.
+    pub count: i32,
+}
+
+impl Item {
+    pub fn def(&self) -> Option<&'static ItemDef> {
+        get(&self.id)
+    }
+}
+
+/// One kind of item, as described in the item data file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub equipment: Option<Equipment>,
+    pub effect: Option<Effect>,
+    /// Set on ammunition, which stacks up in the inventory.
+    pub ammo: Option<Ammo>,
+    /// How many turn up together.
+    pub stack: i32,
+    pub spawn: Vec<Transition>,
+}
+
+/// What a ranged weapon shoots, and what a pile of ammunition is for.
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
-pub enum Item {
-    Heal,
-    Lightning,
-    Confuse,
-    Fireball,
-    Sword,
-    Shield,
-}
-
-pub fn make_item(x: i32, y: i32, item: Item) -> Object {
-    match item {
-        Item::Heal => {
-            let mut object = Object::new(
-                x,
-                y,
-                '!',
-                colors::VIOLET,
-                "healing potion",
-                false,
-            );
-            object.item = Some(Item::Heal);
-            object
-        },
-        Item::Lightning => {
-            let mut object = Object::new(
-                x,
-                y,
-                '#',
-                colors::LIGHT_YELLOW,
-                "scroll of lightning bolt",
-                false,
-            );
-            object.item = Some(Item::Lightning);
-            object
-        },
-        Item::Fireball => {
-            let mut object = Object::new(
-                x,
-                y,
-                '#',
-                colors::LIGHT_YELLOW,
-                "scroll of fireball",
-                false,
-            );
-            object.item = Some(Item::Fireball);
-            object
-        },
-        Item::Confuse => {
-            let mut object = Object::new(
-                x,
-                y,
-                '#',
-                colors::LIGHT_YELLOW,
-                "scroll of confusion",
-                false,
-            );
-            object.item = Some(Item::Confuse);
-            object
-        },
-        Item::Sword => {
-            let sword = Equipment {
-                equipped: false,
-                slot: Slot::RightHand,
-                power_bonus: 3,
-                defense_bonus: 0,
-                max_hp_bonus: 0,
-                movement_bonus: 0,
-                attacks_bonus: 0,
-            };
-            let mut object = Object::new(x, y, '/', colors::SKY, "sword", false);
-            object.item = Some(Item::Sword);
-            object.equipment = Some(sword);
-            object
-        },
-        Item::Shield => {
-            let shield = Equipment {
-                equipped: false,
-                slot: Slot::LeftHand,
-                power_bonus: 0,
-                defense_bonus: 1,
-                max_hp_bonus: 0,
-                movement_bonus: 0,
-                attacks_bonus: 0,
-            };
-            let mut object = Object::new(x, y, '[', colors::SKY, "shield", false);
-            object.item = Some(Item::Shield);
-            object.equipment = Some(shield);
-            object
+pub enum Ammo {
+    Arrow,
+    Bolt,
+    Stone,
+}
+
+impl std::fmt::Display for Ammo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Ammo::Arrow => write!(f, "arrows"),
+            Ammo::Bolt => write!(f, "bolts"),
+            Ammo::Stone => write!(f, "sling stones"),
+        }
+    }
+}
+
+/// The building blocks for what happens when an item is used.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Effect {
+    Heal { amount: i32 },
+    DamageNearest { damage: i32, damage_type: DamageType, range: i32 },
+    DamageArea { damage: i32, damage_type: DamageType, radius: i32 },
+    ApplyStatus { status: Status, turns: i32, range: i32 },
+    SelfStatus { status: Status, turns: i32 },
+}
+
+impl Effect {
+    /// Potions, which are drunk when used and break when thrown.
+    pub fn is_potion(self) -> bool {
+        matches!(self, Effect::Heal { .. } | Effect::SelfStatus { .. })
+    }
+}
+
+static ITEMS: OnceLock<Vec<ItemDef>> = OnceLock::new();
+
+pub fn init(path: &str) -> Result<(), String> {
+    let items = load(path)?;
+    ITEMS.set(items).map_err(|_| "Items were already loaded".to_string())
+}
+
+pub fn all() -> &'static [ItemDef] {
+    ITEMS.get().map_or(&[], |items| items.as_slice())
+}
+
+pub fn get(id: &str) -> Option<&'static ItemDef> {
+    all().iter().find(|item| item.id == id)
+}
+
+fn load(path: &str) -> Result<Vec<ItemDef>, String> {
+    let mut items: Vec<ItemDef> = vec![];
+
+    for mut entry in data::load_entries(path)? {
+        let item = ItemDef {
+            id: entry.field("id")?,
+            name: entry.field("name")?,
+            glyph: entry.field("glyph")?,
+            color: entry.field("color")?,
+            equipment: entry.optional_field("equipment")?,
+            effect: entry.optional_field("effect")?,
+            ammo: entry.optional_field("ammo")?,
+            stack: entry.optional_field("stack")?.unwrap_or(1),
+            spawn: entry.optional_field("spawn")?.unwrap_or_default(),
+        };
+
+        if items.iter().any(|other| other.id == item.id) {
+            return Err(format!("{}: field `id`: `{}` is used twice", entry.context(), item.id));
         }
+
+        entry.finish()?;
+        items.push(item);
     }
+
+    Ok(items)
 }
 
-pub fn item_table_for_level(level: u32) -> Vec<Weighted<Item>> {
-    vec![
-        Weighted {
-            weight: 35,
-            item: Item::Heal,
-        },
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 4,
-                    value: 25,
-                }],
-                level,
-            ),
-            item: Item::Lightning,
-        },
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 6,
-                    value: 25,
-                }],
-                level,
-            ),
-            item: Item::Fireball,
-        },
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 2,
-                    value: 10
-                }],
-                level,
-            ),
-            item: Item::Confuse,
-        },
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 4,
-                    value: 5,
-                }],
-                level,
-            ),
-            item: Item::Sword,
-        },
-        Weighted {
-            weight: from_dungeon_level(
-                &[Transition {
-                    level: 8,
-                    value: 15,
-                }],
-                level,
-            ),
-            item: Item::Shield,
-        },
-    ]
+pub fn make_item(x: i32, y: i32, item: &ItemDef) -> Bundle {
+    let mut bundle = Bundle::from(Object::new(x, y, item.glyph, item.color, &item.name, false));
+
+    bundle.item = Some(Item {
+        id: item.id.clone(),
+        count: item.stack,
+    });
+    bundle.equipment = item.equipment;
+
+    bundle
+}
+
+/// An item found lying about on dungeon level `level`, which may come
+/// enchanted.
+pub fn roll_item(x: i32, y: i32, item: &ItemDef, level: u32, rng: &mut GameRng) -> Bundle {
+    let mut bundle = make_item(x, y, item);
+    affixes::enchant(&mut bundle, level, rng);
+
+    bundle
+}
+
+pub fn item_table_for_level(level: u32) -> Vec<Weighted<&'static ItemDef>> {
+    all()
+        .iter()
+        .map(|item| Weighted {
+            weight: from_dungeon_level(&item.spawn, level),
+            item,
+        })
+        .filter(|entry| entry.weight > 0)
+        .collect()
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Equipment {
     pub slot: Slot,
+    #[serde(default)]
     pub equipped: bool,
+    #[serde(default)]
     pub power_bonus: i32,
+    #[serde(default)]
     pub defense_bonus: i32,
+    #[serde(default)]
     pub max_hp_bonus: i32,
+    #[serde(default)]
     pub movement_bonus: i32,
+    #[serde(default)]
     pub attacks_bonus: i32,
+    /// Set on weapons, which roll these dice instead of the wielder's own.
+    #[serde(default)]
+    pub damage: Option<Dice>,
+    #[serde(default)]
+    pub resistances: Resistances,
+    /// How far a ranged weapon shoots. Zero for everything else.
+    #[serde(default)]
+    pub range: i32,
+    #[serde(default)]
+    pub ammo: Option<Ammo>,
+    /// Takes up both hands, whichever one `slot` says.
+    #[serde(default)]
+    pub two_handed: bool,
+}
+
+impl Equipment {
+    /// Every slot this takes up while it is worn.
+    pub fn occupies(&self) -> Vec<Slot> {
+        if self.two_handed {
+            vec![Slot::LeftHand, Slot::RightHand]
+        } else {
+            vec![self.slot]
+        }
+    }
+
+    /// Where it is worn, for messages.
+    pub fn worn_on(&self) -> String {
+        if self.two_handed {
+            "both hands".to_string()
+        } else {
+            self.slot.to_string()
+        }
+    }
 }
 
+/// Where something is worn. The slot in the item data is only where it goes
+/// by preference: anything for one hand or one finger will go on the other
+/// if that one is free.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Slot {
     LeftHand,
     RightHand,
     Head,
+    Body,
+    Feet,
+    Hands,
+    Cloak,
+    LeftRing,
+    RightRing,
+    Amulet,
+}
+
+impl Slot {
+    pub const ALL: [Slot; 10] = [
+        Slot::Head,
+        Slot::Amulet,
+        Slot::Cloak,
+        Slot::Body,
+        Slot::LeftHand,
+        Slot::RightHand,
+        Slot::Hands,
+        Slot::LeftRing,
+        Slot::RightRing,
+        Slot::Feet,
+    ];
+
+    /// The other one of a pair.
+    pub fn partner(self) -> Option<Slot> {
+        match self {
+            Slot::LeftHand => Some(Slot::RightHand),
+            Slot::RightHand => Some(Slot::LeftHand),
+            Slot::LeftRing => Some(Slot::RightRing),
+            Slot::RightRing => Some(Slot::LeftRing),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Slot {
@@ -190,6 +267,13 @@ impl std::fmt::Display for Slot {
             Slot::LeftHand => write!(f, "left hand"),
             Slot::RightHand => write!(f, "right hand"),
             Slot::Head => write!(f, "head"),
+            Slot::Body => write!(f, "body"),
+            Slot::Feet => write!(f, "feet"),
+            Slot::Hands => write!(f, "hands"),
+            Slot::Cloak => write!(f, "shoulders"),
+            Slot::LeftRing => write!(f, "left ring finger"),
+            Slot::RightRing => write!(f, "right ring finger"),
+            Slot::Amulet => write!(f, "neck"),
         }
     }
 }
@@ -204,86 +288,131 @@ pub enum UseResult {
 
 pub fn use_item(
     inventory_id: usize,
-    objects: &mut [Object],
+    objects: &mut ObjectStore,
     game: &mut Game,
     tcod: &mut Tcod,
 ) {
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use = match item {
-            Item::Heal => cast_heal,
-            Item::Lightning => cast_lightning,
-            Item::Confuse => cast_confuse,
-            Item::Fireball => cast_fireball,
-            Item::Sword => toggle_equipment,
-            Item::Shield => toggle_equipment,
-        };
+    let item = &inventory_of(objects, PLAYER)[inventory_id];
+    let def = item.item.as_ref().and_then(Item::def);
+    let is_equipment = item.equipment.is_some();
+    let name = item.object.name.clone();
+
+    let result = match def.and_then(|def| def.effect) {
+        Some(effect) => apply_effect(effect, &name, objects, game, tcod),
+        None if is_equipment => toggle_equipment(inventory_id, objects, game, tcod),
+        None => {
+            game.log.gutter_text(
+                format!("The {} cannot be used.", name),
+                colors::WHITE
+            );
+            return;
+        }
+    };
 
-        match on_use(inventory_id, objects, game, tcod) {
-            UseResult::UsedUp => {
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::Cancelled => {
-                game.log.gutter_text("Cancelled", colors::WHITE);
-            }
-            UseResult::UsedAndKept => {}
+    match result {
+        UseResult::UsedUp => {
+            inventory_mut(objects, PLAYER).remove(inventory_id);
         }
-    } else {
-        game.log.gutter_text(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            colors::WHITE
-        );
+        UseResult::Cancelled => {
+            game.log.gutter_text("Cancelled", colors::WHITE);
+        }
+        UseResult::UsedAndKept => {}
+    }
+}
+
+pub fn apply_effect(
+    effect: Effect,
+    source_name: &str,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+    tcod: &mut Tcod,
+) -> UseResult {
+    match effect {
+        Effect::Heal { amount } => cast_heal(source_name, amount, objects, game),
+        Effect::DamageNearest { damage, damage_type, range } => {
+            cast_damage_nearest(source_name, damage, damage_type, range, objects, game, tcod)
+        }
+        Effect::DamageArea { damage, damage_type, radius } => {
+            cast_damage_area(source_name, damage, damage_type, radius, objects, game, tcod)
+        }
+        Effect::ApplyStatus { status, turns, range } => {
+            cast_status(source_name, status, turns, range, objects, game, tcod)
+        }
+        Effect::SelfStatus { status, turns } => cast_self_status(source_name, status, turns, objects, game),
     }
 }
 
 pub fn toggle_equipment(
     inventory_id: usize,
-    _objects: &mut [Object],
+    objects: &mut ObjectStore,
     game: &mut Game,
     _tcod: &mut Tcod,
 ) -> UseResult {
-    let equipment = match game.inventory[inventory_id].equipment {
+    let inventory = inventory_mut(objects, PLAYER);
+
+    let equipment = match inventory[inventory_id].equipment {
         Some(equipment) => equipment,
         None => return UseResult::Cancelled,
     };
 
-    if let Some(old_equipment) = get_equipped_in_slot(equipment.slot, &game.inventory) {
-        game.inventory[old_equipment].dequip(&mut game.log);
+    if equipment.equipped {
+        inventory[inventory_id].dequip(&mut game.log);
+        return UseResult::UsedAndKept;
     }
 
-    if equipment.equipped {
-        game.inventory[inventory_id].dequip(&mut game.log);
-    } else {
-        game.inventory[inventory_id].equip(&mut game.log);
+    // Use a free slot if it fits one, otherwise take off whatever is where
+    // it wants to go.
+    let slot = match free_slot(&equipment, inventory) {
+        Some(slot) => slot,
+        None => {
+            for slot in equipment.occupies() {
+                if let Some(old_equipment) = get_equipped_in_slot(slot, inventory) {
+                    inventory[old_equipment].dequip(&mut game.log);
+                }
+            }
+            equipment.slot
+        }
+    };
+
+    if let Some(ref mut equipment) = inventory[inventory_id].equipment {
+        equipment.slot = slot;
     }
+    inventory[inventory_id].equip(&mut game.log);
 
     UseResult::UsedAndKept
 }
 
 pub fn pick_item_up(
-    object_id: usize,
-    objects: &mut Vec<Object>,
+    object_id: EntityId,
+    objects: &mut ObjectStore,
     game: &mut Game,
 ) {
-    if game.inventory.len() >= 26 {
+    let stacks = objects
+        .items
+        .get(object_id)
+        .is_some_and(|item| stack_index(inventory_of(objects, PLAYER), item).is_some());
+
+    if inventory_of(objects, PLAYER).len() >= 26 && !stacks {
         game.log.gutter_text(
             format!("Your inventory is full, cannot pick up {}.", objects[object_id].name),
             colors::RED,
         )
     } else {
-        let item = objects.swap_remove(object_id);
+        let item = objects.remove(object_id).expect("The item to pick up is gone");
 
         game.log.gutter_text(
-            format!("You picked up a {}!", item.name),
+            format!("You picked up a {}!", item_name(&item)),
             colors::GREEN,
         );
-        let index = game.inventory.len();
-        let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
+        let inventory = inventory_mut(objects, PLAYER);
+        let slot = item.equipment.and_then(|equipment| free_slot(&equipment, inventory));
+        let index = stack_item(inventory, item);
 
         if let Some(slot) = slot {
-            if get_equipped_in_slot(slot, &game.inventory).is_none() {
-                game.inventory[index].equip(&mut game.log);
+            if let Some(ref mut equipment) = inventory[index].equipment {
+                equipment.slot = slot;
             }
+            inventory[index].equip(&mut game.log);
         }
     }
 }
@@ -291,26 +420,268 @@ pub fn pick_item_up(
 pub fn drop_item(
     inventory_id: usize,
     game: &mut Game,
-    objects: &mut Vec<Object>,
+    objects: &mut ObjectStore,
 ) {
-    let mut item = game.inventory.remove(inventory_id);
-
-    item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+    let mut item = inventory_mut(objects, PLAYER).remove(inventory_id);
+    if let Some(ref mut equipment) = item.equipment {
+        equipment.equipped = false;
+    }
 
     game.log.gutter_text(
-        format!("You dropped a {}.", item.name),
+        format!("You dropped a {}.", item_name(&item)),
         colors::YELLOW,
     );
 
-    objects.push(item);
+    let (x, y) = objects[PLAYER].pos();
+    let id = objects.insert(item);
+    objects.set_pos(id, x, y);
 }
 
-pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+/// Throw one of whatever is at `inventory_id` at a spot the player picks.
+/// Potions shatter over everyone nearby, and weapons that hit someone hurt
+/// them. Everything else just lands.
+/// Returns true if a weapon was thrown at a creature, which costs an
+/// attack.
+pub fn throw_item(
+    inventory_id: usize,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+    tcod: &mut Tcod,
+) -> bool {
+    let name = inventory_of(objects, PLAYER)[inventory_id].object.name.clone();
+
+    game.log.gutter_text(
+        format!("Left-click where to throw the {}, or right-click to cancel.", name),
+        colors::LIGHT_CYAN,
+    );
+    let target = match target_tile(tcod, objects, game, Some(THROW_RANGE as f32)) {
+        Some(target) => target,
+        None => {
+            game.log.gutter_text("Cancelled", colors::WHITE);
+            return false;
+        }
+    };
+
+    if let Some(ally_id) = objects.fighters_at(target.0, target.1).find(|&id| is_ally(objects, id)) {
+        game.log.gutter_text(
+            format!("You won't throw the {} at the {}.", name, objects[ally_id].name),
+            colors::WHITE,
+        );
+        return false;
+    }
+
+    let ((x, y), hit) = projectile_path(target, objects, &game.map);
+
+    // Whatever goes down a chasm is gone, and the run can't be won without
+    // the crown.
+    let is_goal = inventory_of(objects, PLAYER)[inventory_id]
+        .item
+        .as_ref()
+        .is_some_and(|item| item.id == GOAL_ITEM);
+    if is_goal && game.map[x as usize][y as usize].kind == TileKind::Chasm {
+        game.log.gutter_text(
+            format!("You daren't throw the {} into the chasm.", name),
+            colors::WHITE,
+        );
+        return false;
+    }
+
+    let mut item = take_one(inventory_mut(objects, PLAYER), inventory_id);
+    if let Some(ref mut equipment) = item.equipment {
+        equipment.equipped = false;
+    }
+
+    game.log.gutter_text(format!("You throw the {}.", name), colors::WHITE);
+
+    let effect = item.item.as_ref().and_then(Item::def).and_then(|def| def.effect);
+    if let Some(effect) = effect.filter(|effect| effect.is_potion()) {
+        shatter(&name, effect, x, y, objects, game);
+        return false;
+    }
+
+    let mut landing = (x, y);
+    let mut attacked = false;
+    if let Some(target_id) = hit {
+        match item.equipment.and_then(|equipment| equipment.damage) {
+            Some(dice) => {
+                attacked = true;
+                let events = combat::resolve_shot(PLAYER, target_id, dice, &name, objects, game);
+                if !events.iter().any(|event| matches!(event, CombatEvent::Hit { .. })) {
+                    landing = scatter(x, y, game);
+                }
+                combat::publish(events, game);
+            }
+            None => {
+                game.log.gutter_text(
+                    format!("The {} bounces off the {}.", name, objects[target_id].name),
+                    colors::WHITE,
+                );
+            }
+        }
+    }
+
+    land_item(item, landing.0, landing.1, objects, game);
+
+    attacked
+}
+
+/// Set down something that has been thrown or shot on `(x, y)`. If that is
+/// a chasm it falls out of reach for good.
+pub fn land_item(item: Bundle, x: i32, y: i32, objects: &mut ObjectStore, game: &mut Game) {
+    if game.map[x as usize][y as usize].kind == TileKind::Chasm {
+        game.log.gutter_text(
+            format!("The {} falls into the chasm and is lost.", item_name(&item)),
+            colors::LIGHT_GREY,
+        );
+        return;
+    }
+
+    let id = objects.insert(item);
+    objects.set_pos(id, x, y);
+}
+
+/// A thrown potion breaks and everyone within `SPLASH_RADIUS` gets a taste.
+fn shatter(name: &str, effect: Effect, x: i32, y: i32, objects: &mut ObjectStore, game: &mut Game) {
+    game.log.gutter_text(format!("The {} shatters!", name), colors::LIGHT_VIOLET);
+
+    for id in objects.ids() {
+        if objects[id].distance(x, y) > SPLASH_RADIUS as f32 || !objects.fighters.contains(id) {
+            continue;
+        }
+
+        let source = item_source(name);
+        let events = match effect {
+            Effect::Heal { amount } => combat::resolve_heal(id, amount, source, objects),
+            Effect::SelfStatus { status, turns } => combat::resolve_status(id, status, turns, source, objects),
+            _ => vec![],
+        };
+        combat::publish(events, game);
+    }
+}
+
+pub fn inventory_of(objects: &ObjectStore, id: EntityId) -> &[Bundle] {
+    objects.inventories.get(id).map_or(&[], |inventory| inventory.as_slice())
+}
+
+/// The inventory of `id`, giving them an empty one if they had none.
+pub fn inventory_mut(objects: &mut ObjectStore, id: EntityId) -> &mut Inventory {
+    if !objects.inventories.contains(id) {
+        objects.inventories.insert(id, vec![]);
+    }
+
+    objects.inventories.get_mut(id).unwrap()
+}
+
+/// Put `item` in the inventory and wear it straight away if its slot is
+/// free, without any messages. For kitting out creatures as they spawn.
+pub fn give_item(inventory: &mut Inventory, mut item: Bundle) {
+    if let Some(ref mut equipment) = item.equipment {
+        match free_slot(equipment, inventory) {
+            Some(slot) => {
+                equipment.slot = slot;
+                equipment.equipped = true;
+            }
+            None => equipment.equipped = false,
+        }
+    }
+
+    inventory.push(item);
+}
+
+/// Put `item` in `inventory`, on top of a pile of the same ammunition if
+/// there is one. Returns where it ended up.
+pub fn stack_item(inventory: &mut Inventory, item: Bundle) -> usize {
+    let pile = item.item.as_ref().and_then(|item| stack_index(inventory, item));
+
+    match pile {
+        Some(index) => {
+            let count = item.item.map_or(1, |item| item.count);
+            if let Some(ref mut pile) = inventory[index].item {
+                pile.count += count;
+            }
+            index
+        }
+        None => {
+            inventory.push(item);
+            inventory.len() - 1
+        }
+    }
+}
+
+/// The pile in `inventory` that `item` would go on top of.
+fn stack_index(inventory: &[Bundle], item: &Item) -> Option<usize> {
+    let stackable = item.def().is_some_and(|def| def.ammo.is_some());
+    if !stackable {
+        return None;
+    }
+
+    inventory
+        .iter()
+        .position(|other| other.item.as_ref().is_some_and(|other| other.id == item.id))
+}
+
+/// Take a single item off the pile at `index`, or the whole thing if there
+/// is only one.
+pub fn take_one(inventory: &mut Inventory, index: usize) -> Bundle {
+    let single = match inventory[index].item {
+        Some(ref mut item) if item.count > 1 => {
+            item.count -= 1;
+            item.def()
+        }
+        _ => None,
+    };
+
+    match single {
+        Some(def) => {
+            let (x, y) = inventory[index].object.pos();
+            let mut bundle = make_item(x, y, def);
+            if let Some(ref mut item) = bundle.item {
+                item.count = 1;
+            }
+            bundle
+        }
+        None => inventory.remove(index),
+    }
+}
+
+/// The first pile of `ammo` in `inventory`.
+pub fn find_ammo(inventory: &[Bundle], ammo: Ammo) -> Option<usize> {
+    inventory.iter().position(|item| {
+        item.item
+            .as_ref()
+            .and_then(Item::def)
+            .is_some_and(|def| def.ammo == Some(ammo))
+    })
+}
+
+/// The name to show for an item, with the size of the pile if it is one.
+pub fn item_name(item: &Bundle) -> String {
+    match item.item {
+        Some(Item { count, .. }) if count > 1 => format!("{} ({})", item.object.name, count),
+        _ => item.object.name.clone(),
+    }
+}
+
+/// Somewhere `equipment` could go without taking anything else off: the
+/// slot it prefers, or the other one of the pair.
+fn free_slot(equipment: &Equipment, inventory: &[Bundle]) -> Option<Slot> {
+    std::iter::once(equipment.slot)
+        .chain(equipment.slot.partner())
+        .find(|&slot| {
+            let placed = Equipment { slot, ..*equipment };
+            placed
+                .occupies()
+                .into_iter()
+                .all(|slot| get_equipped_in_slot(slot, inventory).is_none())
+        })
+}
+
+pub fn get_equipped_in_slot(slot: Slot, inventory: &[Bundle]) -> Option<usize> {
     for (inventory_id, item) in inventory.iter().enumerate() {
         if item
             .equipment
             .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot)
+            .map_or(false, |e| e.equipped && e.occupies().contains(&slot))
         {
             return Some(inventory_id);
         }
@@ -320,13 +691,13 @@ pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
 }
 
 fn cast_heal(
-    _inventory_id: usize,
-    objects: &mut [Object],
+    source_name: &str,
+    amount: i32,
+    objects: &mut ObjectStore,
     game: &mut Game,
-    tcod: &mut Tcod,
 ) -> UseResult {
-    if let Some(fighter) = objects[PLAYER].fighter {
-        if fighter.hp == objects[PLAYER].max_hp(game) {
+    if let Some(&fighter) = objects.fighters.get(PLAYER) {
+        if fighter.hp == objects.max_hp(PLAYER) {
             game.log.gutter_text(
                 "You are already at full health.",
                 colors::RED,
@@ -334,39 +705,30 @@ fn cast_heal(
 
             return UseResult::Cancelled;
         }
-        game.log.gutter_text(
-            "Your wounds start to feel better!",
-            colors::LIGHT_VIOLET,
-        );
-
-        objects[PLAYER].heal(HEAL_AMOUNT, game);
+        let source = item_source(source_name);
+        let events = combat::resolve_heal(PLAYER, amount, source, objects);
+        combat::publish(events, game);
         return UseResult::UsedUp;
     }
 
     UseResult::Cancelled
 }
 
-fn cast_lightning(
-    _inventory_id: usize,
-    objects: &mut [Object],
+fn cast_damage_nearest(
+    source_name: &str,
+    damage: i32,
+    damage_type: DamageType,
+    range: i32,
+    objects: &mut ObjectStore,
     game: &mut Game,
     tcod: &mut Tcod,
 ) -> UseResult {
-    let monster_id = closest_monster(LIGHTNING_RANGE, objects, tcod);
+    let monster_id = closest_monster(range, objects, tcod);
 
     if let Some(monster_id) = monster_id {
-        game.log.gutter_text(
-            format!(
-                "The lightning bolt strikes the {} with a loud thunder! \
-                 The damage is {} hit points.",
-                objects[monster_id].name, LIGHTNING_DAMAGE
-            ),
-            colors::LIGHT_BLUE,
-        );
-
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
-            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
-        }
+        let source = item_source(source_name);
+        let events = combat::resolve_damage(monster_id, damage, damage_type, source, objects, game);
+        combat::publish(events, game);
 
         UseResult::UsedUp
     } else {
@@ -375,14 +737,17 @@ fn cast_lightning(
     }
 }
 
-fn cast_fireball(
-    _inventory_id: usize,
-    objects: &mut [Object],
+fn cast_damage_area(
+    source_name: &str,
+    damage: i32,
+    damage_type: DamageType,
+    radius: i32,
+    objects: &mut ObjectStore,
     game: &mut Game,
     tcod: &mut Tcod,
 ) -> UseResult {
     game.log.gutter_text(
-        "Left-click a target tile for the fireball, or right-clock to cancel.",
+        format!("Left-click a target tile for the {}, or right-clock to cancel.", source_name),
         colors::LIGHT_CYAN,
     );
 
@@ -392,59 +757,40 @@ fn cast_fireball(
     };
     game.log.gutter_text(
         format!(
-            "The fireball explodes, burning everything within {} tiles!",
-            FIREBALL_RADIUS,
+            "The {} explodes, hitting everything within {} tiles!",
+            source_name, radius,
         ),
         colors::ORANGE,
     );
 
-    let mut xp_to_gain = 0;
-    for (id, obj) in objects.iter_mut().enumerate() {
-        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
-            game.log.gutter_text(
-                format!(
-                    "The {} gets burned for {} hitpoints.",
-                    obj.name, FIREBALL_DAMAGE
-                ),
-                colors::ORANGE,
-            );
-
-            if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
-                if id != PLAYER {
-                    xp_to_gain += xp;
-                }
-            }
+    // Companions know to duck when they see a spell coming; the player is on
+    // their own.
+    for id in objects.ids() {
+        if objects[id].distance(x, y) <= radius as f32 && objects.fighters.contains(id) && !is_ally(objects, id) {
+            let source = item_source(source_name);
+            let events = combat::resolve_damage(id, damage, damage_type, source, objects, game);
+            combat::publish(events, game);
         }
     }
 
-    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
-
     UseResult::UsedUp
 }
 
-fn cast_confuse(
-    _inventory_id: usize,
-    objects: &mut [Object],
+fn cast_status(
+    source_name: &str,
+    status: Status,
+    turns: i32,
+    range: i32,
+    objects: &mut ObjectStore,
     game: &mut Game,
     tcod: &mut Tcod,
 ) -> UseResult {
-    let monster_id = target_monster(tcod, objects, game, Some(CONFUSE_RANGE as f32));
+    let monster_id = target_monster(tcod, objects, game, Some(range as f32));
 
     if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-
-        objects[monster_id].ai = Some(Ai::Confused {
-            previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
-        });
-
-        game.log.gutter_text(
-            format!(
-                "The eyes of {} look vacant, as he starts to stumble around!",
-                objects[monster_id].name
-            ),
-            colors::LIGHT_GREEN,
-        );
+        let source = item_source(source_name);
+        let events = combat::resolve_status(monster_id, status, turns, source, objects);
+        combat::publish(events, game);
 
         UseResult::UsedUp
     } else {
@@ -455,3 +801,25 @@ fn cast_confuse(
         UseResult::Cancelled
     }
 }
+
+fn cast_self_status(
+    source_name: &str,
+    status: Status,
+    turns: i32,
+    objects: &mut ObjectStore,
+    game: &mut Game,
+) -> UseResult {
+    let source = item_source(source_name);
+    let events = combat::resolve_status(PLAYER, status, turns, source, objects);
+    combat::publish(events, game);
+
+    UseResult::UsedUp
+}
+
+/// Scrolls and potions are only ever used by the player.
+fn item_source(name: &str) -> Source {
+    Source::Item {
+        name: name.to_string(),
+        user: PLAYER,
+    }
+}
@@ -15,6 +15,15 @@ mod items;
 mod monsters;
 mod ai;
 mod display;
+mod rng;
+mod mapgen;
+mod vaults;
+mod data;
+mod entity;
+mod status;
+mod combat;
+mod faction;
+mod affixes;
 
 use tcod::{
     console::*,
@@ -22,20 +31,27 @@ use tcod::{
     input::*,
     input::{self, Event, EventFlags, KeyCode::*, Mouse},
     map::{Map as FovMap, *},
+    line::Line,
 };
 use std::io::{Read, Write};
 use std::fs::File;
 use std::error::Error;
 use std::cmp;
+use std::collections::HashMap;
 use std::ops::Range;
 use rand::{distributions::{IndependentSample, Weighted, WeightedChoice}, Rng};
 
 use consts::*;
-use types::{DeathCallback, Fighter, Game, Map, Object, PlayerAction, Tcod, Tile, attack};
+use types::{DeathCallback, Fighter, Game, Level, Map, Object, PlayerAction, Tcod, Tile, TileEffect, TileKind};
 use logging::*;
 use items::*;
 use ai::{Ai, ai_take_turn, move_by};
 use util::*;
+use rng::GameRng;
+use combat::{CombatEvent, CombatStats, DamageType, Dice, Resistances, Source, attack};
+use faction::{Faction, are_hostile, is_ally};
+use status::{Status, has_status, tick_statuses};
+use entity::{Bundle, EntityId, ObjectStore};
 
 mod logging {
     use crate::types::*;
@@ -60,11 +76,11 @@ mod logging {
     }
 }
 
-fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
-    let player = &mut objects[PLAYER];
-    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+fn level_up(objects: &mut ObjectStore, game: &mut Game, tcod: &mut Tcod) {
+    let level_up_xp = LEVEL_UP_BASE + objects[PLAYER].level * LEVEL_UP_FACTOR;
 
-    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
+    if objects.fighters.get(PLAYER).map_or(0, |f| f.xp) >= level_up_xp {
+        let player = &mut objects[PLAYER];
         player.level += 1;
         game.gutter_text(
             format!(
@@ -75,7 +91,7 @@ fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
         );
 
 
-        let fighter = player.fighter.as_mut().unwrap();
+        let fighter = objects.fighters.get_mut(PLAYER).unwrap();
         let mut choice = None;
 
         while choice.is_none() {
@@ -107,17 +123,18 @@ fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
     }
 }
 
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut ObjectStore) {
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
 
-    let target_id = objects.
-        iter().
-        position(|object| object.fighter.is_some() && object.pos() == (x, y));
+    let target_id = objects.fighters_at(x, y).next();
 
     match target_id {
+        Some(ally_id) if is_ally(objects, ally_id) => {
+            swap_places(PLAYER, ally_id, objects);
+        }
         Some(target_id) => {
-            attack(PLAYER, target_id, objects, game);
+            attack(PLAYER, target_id, 0, objects, game);
         }
         None => {
             move_by(PLAYER, dx, dy, &game.map, objects);
@@ -125,33 +142,164 @@ fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Objec
     }
 }
 
-fn has_attackable_target(x: i32, y: i32, objects: &[Object]) -> Option<usize> {
-    objects
-        .iter()
-        .position(|o| o.fighter.is_some() && o.pos() == (x, y))
+fn has_attackable_target(x: i32, y: i32, objects: &ObjectStore) -> Option<EntityId> {
+    objects.entities_at(x, y).find(|&id| are_hostile(objects, PLAYER, id))
+}
+
+/// Does the player have a ranged weapon, something to shoot from it, and
+/// an enemy in range to shoot at?
+fn can_shoot(objects: &mut ObjectStore, tcod: &Tcod) -> bool {
+    why_cannot_shoot(objects, tcod).is_none()
+}
+
+/// What is stopping the player from shooting, if anything.
+fn why_cannot_shoot(objects: &mut ObjectStore, tcod: &Tcod) -> Option<String> {
+    let weapon = match objects.ranged_weapon(PLAYER) {
+        Some(weapon) => weapon,
+        None => return Some("You have nothing to shoot with.".to_string()),
+    };
+    let ammo = weapon.ammo.expect("Ranged weapons always take ammunition");
+
+    if items::find_ammo(inventory_of(objects, PLAYER), ammo).is_none() {
+        Some(format!("You are out of {}.", ammo))
+    } else if closest_monster(weapon.range, objects, tcod).is_none() {
+        Some("There is nothing in range to shoot at.".to_string())
+    } else {
+        None
+    }
+}
+
+/// Shoot the player's ranged weapon at a target they pick. Returns true if
+/// a shot was taken.
+fn fire(objects: &mut ObjectStore, game: &mut Game, tcod: &mut Tcod) -> bool {
+    let weapon = match objects.ranged_weapon(PLAYER) {
+        Some(weapon) => weapon,
+        None => {
+            game.gutter_text("You have nothing to shoot with.", colors::WHITE);
+            return false;
+        }
+    };
+    let ammo = weapon.ammo.expect("Ranged weapons always take ammunition");
+
+    let ammo_index = match items::find_ammo(inventory_of(objects, PLAYER), ammo) {
+        Some(index) => index,
+        None => {
+            game.gutter_text(format!("You are out of {}.", ammo), colors::WHITE);
+            return false;
+        }
+    };
+
+    game.gutter_text(
+        "Left-click an enemy to shoot at, or right-click to cancel.",
+        colors::LIGHT_CYAN,
+    );
+    let target_id = match target_monster(tcod, objects, game, Some(weapon.range as f32)) {
+        Some(target_id) => target_id,
+        None => return false,
+    };
+
+    let projectile = items::take_one(inventory_mut(objects, PLAYER), ammo_index);
+    let dice = weapon.damage.unwrap_or(Dice::new(1, 2));
+    let (x, y) = objects[target_id].pos();
+
+    let events = combat::resolve_shot(PLAYER, target_id, dice, &projectile.object.name, objects, game);
+    let hit = events.iter().any(|event| matches!(event, CombatEvent::Hit { .. }));
+    combat::publish(events, game);
+
+    // The spent shot drops where the target stood, or skitters past it.
+    let (land_x, land_y) = if hit { (x, y) } else { scatter(x, y, game) };
+    items::land_item(projectile, land_x, land_y, objects, game);
+
+    true
+}
+
+/// Pick something to throw and throw it. Returns true if that used up an
+/// attack.
+fn throw(objects: &mut ObjectStore, game: &mut Game, tcod: &mut Tcod) -> bool {
+    let inventory_index = display::inventory_menu(
+        inventory_of(objects, PLAYER),
+        "Press the key next to an item to throw it, or any other to cancel.\n",
+        &mut tcod.root,
+    );
+
+    match inventory_index {
+        Some(inventory_index) => throw_item(inventory_index, objects, game, tcod),
+        None => false,
+    }
 }
 
+/// Somewhere next to `x, y` that isn't inside a wall.
+pub fn scatter(x: i32, y: i32, game: &mut Game) -> (i32, i32) {
+    let (land_x, land_y) = (x + game.rng.gen_range(-1, 2), y + game.rng.gen_range(-1, 2));
+    let on_map = (0..MAP_WIDTH).contains(&land_x) && (0..MAP_HEIGHT).contains(&land_y);
+
+    if on_map && !game.map[land_x as usize][land_y as usize].blocked() {
+        (land_x, land_y)
+    } else {
+        (x, y)
+    }
+}
+
+/// Follow something flung from the player towards `target`. It stops at
+/// the first enemy in the way, or just short of a wall; companions know to
+/// duck. Returns where it comes down and who, if anyone, it hit.
+pub fn projectile_path(target: (i32, i32), objects: &ObjectStore, map: &Map) -> ((i32, i32), Option<EntityId>) {
+    let start = objects[PLAYER].pos();
+    let mut landing = start;
+
+    for (x, y) in Line::new(start, target) {
+        if map[x as usize][y as usize].blocked() {
+            break;
+        }
+        landing = (x, y);
 
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    if map[x as usize][y as usize].blocked {
+        let hit = objects
+            .fighters_at(x, y)
+            .find(|&id| id != PLAYER && !is_ally(objects, id));
+        if hit.is_some() {
+            return (landing, hit);
+        }
+    }
+
+    (landing, None)
+}
+
+/// Companions step aside for the player rather than get walked into.
+fn swap_places(a: EntityId, b: EntityId, objects: &mut ObjectStore) {
+    let (a_x, a_y) = objects[a].pos();
+    let (b_x, b_y) = objects[b].pos();
+    objects.set_pos(a, b_x, b_y);
+    objects.set_pos(b, a_x, a_y);
+}
+
+
+fn is_blocked(x: i32, y: i32, map: &Map, objects: &ObjectStore) -> bool {
+    if map[x as usize][y as usize].blocked() {
         return true
     }
-    objects.iter().any(|object| object.blocks && object.pos() == (x, y))
+    objects.blocker_at(x, y).is_some()
 }
 
+/// Have the player click on an enemy. Companions can't be picked.
 fn target_monster(
     tcod: &mut Tcod,
-    objects: &mut [Object],
+    objects: &mut ObjectStore,
     game: &mut Game,
     max_range: Option<f32>,
-) -> Option<usize> {
+) -> Option<EntityId> {
     loop {
         match target_tile(tcod, objects, game, max_range) {
             Some((x, y)) => {
-                for (id, obj) in objects.iter().enumerate() {
-                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
-                        return Some(id)
+                let target = objects.fighters_at(x, y).find(|&id| id != PLAYER);
+                match target {
+                    Some(ally_id) if is_ally(objects, ally_id) => {
+                        game.gutter_text(
+                            format!("You won't turn on the {}.", objects[ally_id].name),
+                            colors::WHITE,
+                        );
                     }
+                    Some(_) => return target,
+                    None => {}
                 }
             },
             None => return None,
@@ -159,14 +307,13 @@ fn target_monster(
     }
 }
 
-pub fn closest_monster(max_range: i32, objects: &mut [Object], tcod: &Tcod) -> Option<usize> {
+pub fn closest_monster(max_range: i32, objects: &mut ObjectStore, tcod: &Tcod) -> Option<EntityId> {
     let mut closest_enemy = None;
     let mut closest_dist = (max_range + 1) as f32;
 
-    for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER)
-            && object.fighter.is_some()
-            && object.ai.is_some()
+    for (id, _) in objects.ais.iter() {
+        let object = &objects[id];
+        if are_hostile(objects, PLAYER, id)
             && tcod.fov.is_in_fov(object.x, object.y)
         {
             let dist = objects[PLAYER].distance_to(object);
@@ -182,7 +329,7 @@ pub fn closest_monster(max_range: i32, objects: &mut [Object], tcod: &Tcod) -> O
 
 pub fn target_tile(
     tcod: &mut Tcod,
-    objects: &mut [Object],
+    objects: &mut ObjectStore,
     game: &mut Game,
     max_range: Option<f32>,
 ) -> Option<(i32, i32)> {
@@ -221,230 +368,348 @@ pub fn target_tile(
     }
 }
 
-fn make_map(objects: &mut Vec<Object>) -> Map {
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
-
-    let mut rooms = vec![];
-
-    for _ in 0..MAX_ROOMS {
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+fn make_map(objects: &mut ObjectStore, level: u32, rng: &mut GameRng) -> Map {
+    let player = objects.take_player();
+    *objects = ObjectStore::with_player(player);
 
-        let new_room = Rect::new(x, y, w, h);
+    let mut generated = mapgen::generator_for_level(level).generate(rng);
+    mapgen::add_features(&mut generated, level, rng);
 
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
+    let mut vault_spawns = vec![];
+    let all_vaults = vaults::all();
+    if !all_vaults.is_empty() && rng.gen::<f32>() < VAULT_CHANCE {
+        let vault = &all_vaults[rng.gen_range(0, all_vaults.len())];
+        if let Some(spawns) = vaults::place_vault(&mut generated, vault, rng) {
+            vault_spawns = spawns;
+        }
+    }
 
-        if !failed {
-            create_room(new_room, &mut map);
+    let mut map = generated.map;
 
-            place_objects(new_room, objects, &mut map);
+    let (start_x, start_y) = generated.start;
+    objects.set_pos(PLAYER, start_x, start_y);
 
-            let (new_x, new_y) = new_room.center();
+    for (x, y, spawn) in vault_spawns {
+        objects.insert(match spawn {
+            vaults::Spawn::Monster(monster) => monsters::make_monster(x, y, monster),
+            vaults::Spawn::Item(item) => items::roll_item(x, y, item, level, rng),
+        });
+    }
 
-            if !rooms.is_empty() {
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
+    let (stairs_x, stairs_y) = generated.stairs;
 
-                if rand::random() {
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
-            } else {
-                objects[PLAYER].set_pos(new_x, new_y);
-            }
+    if level < FINAL_LEVEL {
+        let mut stairs = Object::new(
+            stairs_x,
+            stairs_y,
+            '<',
+            colors::WHITE,
+            "down stairs",
+            false,
+        );
 
-            rooms.push(new_room);
+        stairs.always_visible = true;
+        objects.insert(stairs);
+    } else {
+        // The bottom of the dungeon has no way down, just the king where the
+        // stairs would have been. The crown is in his loot. He goes in before
+        // the regions are filled so nothing else spawns on top of him, and
+        // anything a vault put there makes way.
+        let boss = monsters::get(BOSS_MONSTER).expect("No boss in the monster data");
+
+        if let Some(blocker) = objects.blocker_at(stairs_x, stairs_y).filter(|&id| id != PLAYER) {
+            objects.remove(blocker);
         }
+        objects.insert(monsters::make_monster(stairs_x, stairs_y, boss));
     }
 
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    for region in &generated.regions {
+        place_objects(region, objects, &mut map, level, rng);
+    }
 
-    let mut stairs = Object::new(
-        last_room_x,
-        last_room_y,
-        '<',
+    // On the first level these lead out of the dungeon.
+    let mut up_stairs = Object::new(
+        start_x,
+        start_y,
+        '>',
         colors::WHITE,
-        "stairs",
+        "up stairs",
         false,
     );
 
-    stairs.always_visible = true;
-    objects.push(stairs);
+    up_stairs.always_visible = true;
+    objects.insert(up_stairs);
 
     map
 }
 
-fn next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
-    game.log.gutter_text(
-        "You take a moment to rest, and recover your strength.",
-        colors::VIOLET,
-    );
+fn next_level(tcod: &mut Tcod, objects: &mut ObjectStore, game: &mut Game) {
+    let level = game.dungeon_level + 1;
+    change_level(level, tcod, objects, game);
+}
 
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].heal(heal_hp, game);
+fn previous_level(tcod: &mut Tcod, objects: &mut ObjectStore, game: &mut Game) {
+    let level = game.dungeon_level - 1;
+    change_level(level, tcod, objects, game);
+}
 
-    game.log.gutter_text(
-        "After a rare moment of peace, you descend deeper into \
-         the heart of the dungeon..",
-        colors::RED,
-    );
+/// Put the current level away in `game.levels` and bring up `level`,
+/// generating it if this is the first visit. The player arrives on the
+/// stairs leading back to where they came from.
+fn change_level(level: u32, tcod: &mut Tcod, objects: &mut ObjectStore, game: &mut Game) {
+    let going_down = level > game.dungeon_level;
+
+    // Companions that were told to stay wait here for the player to return.
+    let following: Vec<EntityId> = objects
+        .ids()
+        .into_iter()
+        .filter(|&id| objects.ais.get(id) == Some(&Ai::Follow))
+        .collect();
+    let followers: Vec<Bundle> = following
+        .into_iter()
+        .filter_map(|id| objects.remove(id))
+        .collect();
+    let player = objects.take_player();
+
+    let leaving = Level {
+        map: std::mem::take(&mut game.map),
+        objects: std::mem::take(objects),
+    };
+    game.levels.insert(game.dungeon_level, leaving);
+    game.dungeon_level = level;
+
+    match game.levels.remove(&level) {
+        Some(stored) => {
+            game.map = stored.map;
+            *objects = stored.objects;
+            objects.put_player(player);
+
+            game.log.gutter_text(
+                if going_down {
+                    format!("You descend back down to level {}.", level)
+                } else {
+                    format!("You climb back up to level {}.", level)
+                },
+                colors::WHITE,
+            );
+        }
+        None => {
+            objects.put_player(player);
 
-    game.dungeon_level += 1;
-    game.map = make_map(objects);
+            game.log.gutter_text(
+                "You take a moment to rest, and recover your strength.",
+                colors::VIOLET,
+            );
 
-    initialize_fov(&game.map, tcod);
-}
+            let heal_hp = objects.max_hp(PLAYER) / 2;
+            objects.heal(PLAYER, heal_hp);
 
-#[derive(Clone,Copy,Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-}
+            game.log.gutter_text(
+                "After a rare moment of peace, you descend deeper into \
+                 the heart of the dungeon..",
+                colors::RED,
+            );
 
-impl Rect {
-    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect {
-            x1: x,
-            y1: y,
-            x2: x + w,
-            y2: y + h,
+            game.map = make_map(objects, level, &mut game.rng);
         }
     }
 
-    pub fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
+    let arrival = if going_down { "up stairs" } else { "down stairs" };
+    let arrival_pos = objects
+        .objects()
+        .find(|object| object.name == arrival)
+        .map(|object| object.pos());
 
-        (center_x, center_y)
+    if let Some((x, y)) = arrival_pos {
+        objects.set_pos(PLAYER, x, y);
     }
+    place_companions(followers, objects, &game.map);
 
-    pub fn intersects_with(&self, other: &Rect) -> bool {
-        (self.x1 <= other.x2)
-            && (self.x2 >= other.x1)
-            && (self.y1 <= other.y2)
-            && (self.y2 >= other.y1)
-    }
+    initialize_fov(&game.map, tcod);
+    tcod.fov.compute_fov(objects[PLAYER].x, objects[PLAYER].y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
 }
 
-fn create_room(room: Rect, map: &mut Map) {
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+/// Set `companions` down on the free tiles closest to the player.
+fn place_companions(companions: Vec<Bundle>, objects: &mut ObjectStore, map: &Map) {
+    let (x, y) = objects[PLAYER].pos();
+
+    for companion in companions {
+        let id = objects.insert(companion);
+        if let Some((free_x, free_y)) = free_tile_near(x, y, map, objects) {
+            objects.set_pos(id, free_x, free_y);
         }
     }
 }
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+fn free_tile_near(x: i32, y: i32, map: &Map, objects: &ObjectStore) -> Option<(i32, i32)> {
+    for radius in 1..MAP_WIDTH.max(MAP_HEIGHT) {
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                let (tile_x, tile_y) = (x + dx, y + dy);
+                let on_ring = dx.abs() == radius || dy.abs() == radius;
+                let on_map = (0..MAP_WIDTH).contains(&tile_x) && (0..MAP_HEIGHT).contains(&tile_y);
+
+                if on_ring
+                    && on_map
+                    && !is_blocked(tile_x, tile_y, map, objects)
+                    && !map[tile_x as usize][tile_y as usize].kind.is_hazard()
+                {
+                    return Some((tile_x, tile_y));
+                }
+            }
+        }
     }
-}
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
-    }
+    None
 }
 
-fn place_objects(room: Rect, objects: &mut Vec<Object>, map: &mut Map,) {
-    let level = 1;
-    let max_monsters = from_dungeon_level(
+fn max_monsters_per_region(level: u32) -> u32 {
+    from_dungeon_level(
         &[
             Transition { level: 1, value: 2 },
             Transition { level: 4, value: 3 },
             Transition { level: 6, value: 5 },
         ],
         level,
-    );
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
-    let monsters = &mut monsters::monster_table_for_level(level);
-    let monster_choice = WeightedChoice::new(monsters);
-
-    for _ in 0..num_monsters {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        if !is_blocked(x, y, map, objects) {
-            let monster = monsters::make_monster(x, y, monster_choice.ind_sample(&mut rand::thread_rng()));
-            objects.push(monster);
-        }
-    }
+    )
+}
 
-    let max_items = from_dungeon_level(
+fn max_items_per_region(level: u32) -> u32 {
+    from_dungeon_level(
         &[
             Transition { level: 1, value: 1, },
             Transition { level: 4, value: 2, },
         ],
         level,
-    );
+    )
+}
 
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
+fn place_objects(region: &[(i32, i32)], objects: &mut ObjectStore, map: &mut Map, level: u32, rng: &mut GameRng) {
+    let max_monsters = max_monsters_per_region(level);
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
+    let monsters = &mut monsters::monster_table_for_level(level);
+
+    if !monsters.is_empty() {
+        let monster_choice = WeightedChoice::new(monsters);
+
+        for _ in 0..num_monsters {
+            let (x, y) = region[rng.gen_range(0, region.len())];
+
+            if !is_blocked(x, y, map, objects) {
+                let monster = monsters::make_monster(x, y, monster_choice.ind_sample(rng));
+                objects.insert(monster);
+            }
+        }
+    }
+
+    let max_items = max_items_per_region(level);
+    let num_items = rng.gen_range(0, max_items + 1);
 
     for _ in 0..num_items {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let (x, y) = region[rng.gen_range(0, region.len())];
 
         let items = &mut items::item_table_for_level(level);
+        if items.is_empty() {
+            break;
+        }
         let item_choice = WeightedChoice::new(items);
 
         if !is_blocked(x, y, map, objects) {
-            let dice = rand::random::<f32>();
-            let item_type = item_choice.ind_sample(&mut rand::thread_rng());
-            let item = items::make_item(x, y, item_type);
+            let item_type = item_choice.ind_sample(rng);
+            let item = items::roll_item(x, y, item_type, level, rng);
 
-            objects.push(item);
+            objects.insert(item);
         }
     }
 }
 
+/// A plain-text dump of what `place_objects` will roll on `level`, for
+/// checking the difficulty curves without playing down to that depth.
+fn spawn_report(level: u32) -> String {
+    fn weights(table: &[(String, u32)]) -> String {
+        let total: u32 = table.iter().map(|&(_, weight)| weight).sum();
 
-fn new_game(tcod: &mut Tcod) -> (Vec<Object>, Game) {
+        table
+            .iter()
+            .map(|(name, weight)| {
+                let percent = if total > 0 {
+                    *weight as f32 * 100.0 / total as f32
+                } else {
+                    0.0
+                };
+                format!("  {:<18} {:>4} {:>6.1}%\n", name, weight, percent)
+            })
+            .collect()
+    }
+
+    let monster_weights = monsters::monster_table_for_level(level)
+        .iter()
+        .map(|entry| (entry.item.id.clone(), entry.weight))
+        .collect::<Vec<_>>();
+
+    let item_weights = items::item_table_for_level(level)
+        .iter()
+        .map(|entry| (entry.item.id.clone(), entry.weight))
+        .collect::<Vec<_>>();
+
+    format!(
+        "Dungeon level {}\n\
+         Monsters per region: 0-{}\n\
+         Items per region: 0-{}\n\
+         Equipment: {}% enchanted (up to +{}), {}% chance of each affix\n\
+         Monsters:\n{}\
+         Items:\n{}",
+        level,
+        max_monsters_per_region(level),
+        max_items_per_region(level),
+        affixes::enchantment_chance(level),
+        affixes::max_enchantment(level),
+        affixes::affix_chance(level),
+        weights(&monster_weights),
+        weights(&item_weights),
+    )
+}
+
+
+fn new_game(seed: u64, tcod: &mut Tcod) -> (ObjectStore, Game) {
     let mut player = Object::new(0, 0, '@', colors::WHITE, "player", true);
     player.alive = true;
+
+    let mut player = Bundle::from(player);
     player.fighter = Some(Fighter {
         base_max_hp: 100,
         hp: 100,
         base_defense: 1,
         base_power: 2,
+        damage: Dice::new(1, 3),
+        resistances: Resistances::default(),
+        faction: Faction::Player,
         on_death: DeathCallback::Player,
         xp: 0,
         base_movement: 4,
         base_attacks: 1,
     });
 
-    let mut objects = vec![player];
+    let dagger = items::make_item(0, 0, items::get("dagger").expect("No dagger in the item data"));
+    let mut inventory = vec![];
+    items::give_item(&mut inventory, dagger);
+    player.inventory = Some(inventory);
+
+    let mut objects = ObjectStore::with_player(player);
+    let mut rng = GameRng::from_seed(seed);
 
     let mut game = Game {
-        map: make_map(&mut objects),
+        map: make_map(&mut objects, 1, &mut rng),
         log: vec![],
-        inventory: vec![],
         dungeon_level: 1,
+        levels: HashMap::new(),
+        seed,
+        rng,
+        stats: CombatStats::default(),
     };
 
-    let mut dagger = Object::new(0, 0, '-', colors::SKY, "dagger", false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2,
-        movement_bonus: 0,
-        attacks_bonus: 0,
-    });
-
-    game.inventory.push(dagger);
+    let dog = monsters::make_monster(0, 0, monsters::get(STARTING_COMPANION).expect("No dog in the monster data"));
+    place_companions(vec![dog], &mut objects, &game.map);
 
     initialize_fov(&game.map, tcod);
 
@@ -462,8 +727,8 @@ fn initialize_fov(map: &Map, tcod: &mut Tcod) {
             tcod.fov.set(
                 x,
                 y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
+                !map[x as usize][y as usize].block_sight(),
+                !map[x as usize][y as usize].blocked(),
             );
         }
     }
@@ -471,14 +736,44 @@ fn initialize_fov(map: &Map, tcod: &mut Tcod) {
     tcod.con.clear();
 }
 
+fn set_tile_kind(x: i32, y: i32, kind: TileKind, game: &mut Game, tcod: &mut Tcod) {
+    let tile = &mut game.map[x as usize][y as usize];
+    tile.kind = kind;
+    tcod.fov.set(x, y, !tile.block_sight(), !tile.blocked());
+}
+
+/// Apply the effect of whatever the player has just stepped onto. Returns
+/// true if the player has left the level.
+fn enter_tile(objects: &mut ObjectStore, game: &mut Game, tcod: &mut Tcod) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+
+    match game.map[x as usize][y as usize].kind.on_enter() {
+        TileEffect::Nothing => false,
+        TileEffect::Burn(damage) => {
+            let source = Source::Terrain("lava".to_string());
+            let events = combat::resolve_damage(PLAYER, damage, DamageType::Fire, source, objects, game);
+            combat::publish(events, game);
+            false
+        }
+        TileEffect::Fall => {
+            game.gutter_text(
+                "You tumble into the chasm and land on the level below!",
+                colors::RED,
+            );
+            next_level(tcod, objects, game);
+            true
+        }
+    }
+}
+
 fn play_game(
-    objects: &mut Vec<Object>,
+    objects: &mut ObjectStore,
     game: &mut Game,
     tcod: &mut Tcod,
 ) {
     tcod.fov.compute_fov(objects[PLAYER].x, objects[PLAYER].y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
 
-    fn player_next_action(objects: &mut Vec<Object>, tcod: &mut Tcod, game: &mut Game) -> PlayerAction {
+    fn player_next_action(objects: &mut ObjectStore, tcod: &mut Tcod, game: &mut Game) -> PlayerAction {
         let mut key = Default::default();
         match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
             Some((_, Event::Mouse(m))) => tcod.mouse = m,
@@ -503,10 +798,21 @@ fn play_game(
         );
         tcod.root.flush();
 
+        if !objects[PLAYER].alive {
+            game_over(objects, game, tcod);
+            return;
+        }
+
+        let stunned = has_status(objects, PLAYER, Status::Stunned);
         let mut pinned = is_pinned(PLAYER, objects);
-        let mut remaining_moves = objects[PLAYER].movement(game);
+        let mut remaining_moves = objects.movement(PLAYER);
+        // Throwing a weapon while on the move comes out of the attacks.
+        let mut attacks_spent = 0;
 
-        if pinned {
+        if stunned {
+            remaining_moves = 0;
+            game.gutter_text("You are stunned and cannot act!", colors::WHITE);
+        } else if pinned {
             remaining_moves = 0;
             game.gutter_text("You are pinned in place and cannot move", colors::WHITE);
         } else {
@@ -521,22 +827,65 @@ fn play_game(
                 }
                 PlayerAction::DidntTakeTurn => {}
                 PlayerAction::Move(dx, dy) => {
+                    // A confused player staggers off in any direction.
+                    let (dx, dy) = if has_status(objects, PLAYER, Status::Confused) {
+                        (game.rng.gen_range(-1, 2), game.rng.gen_range(-1, 2))
+                    } else {
+                        (dx, dy)
+                    };
+
                     let x = objects[PLAYER].x + dx;
                     let y = objects[PLAYER].y + dy;
 
                     let tile_is_blocked = is_blocked(x, y, &game.map, objects);
+                    let ally = objects.blocker_at(x, y).filter(|&id| is_ally(objects, id));
+                    let kind = game.map[x as usize][y as usize].kind;
 
-                    if tile_is_blocked {
+                    if kind == TileKind::ClosedDoor {
+                        set_tile_kind(x, y, TileKind::OpenDoor, game, tcod);
+                        game.gutter_text("You open the door.", colors::WHITE);
+                        tcod.fov.compute_fov(objects[PLAYER].x, objects[PLAYER].y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+                        remaining_moves -= 1;
+                    } else if tile_is_blocked && ally.is_none() {
                         game.gutter_text("You cannot move there, it is blocked", colors::WHITE);
                     } else {
-                        move_by(PLAYER, dx, dy, &game.map, objects);
+                        match ally {
+                            Some(ally_id) => swap_places(PLAYER, ally_id, objects),
+                            None => move_by(PLAYER, dx, dy, &game.map, objects),
+                        }
                         tcod.fov.compute_fov(objects[PLAYER].x, objects[PLAYER].y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
-                        remaining_moves -= 1;
+                        remaining_moves -= kind.movement_cost();
+
+                        if enter_tile(objects, game, tcod) {
+                            remaining_moves = 0;
+                        }
                     }
                 }
                 PlayerAction::EndedMove => {
                     remaining_moves = 0;
                 }
+                PlayerAction::Fire => match why_cannot_shoot(objects, tcod) {
+                    Some(reason) => game.gutter_text(reason, colors::WHITE),
+                    None => {
+                        game.gutter_text("You stop to take aim.", colors::WHITE);
+                        remaining_moves = 0;
+                    }
+                },
+                PlayerAction::Throw => {
+                    if throw(objects, game, tcod) {
+                        attacks_spent += 1;
+                        remaining_moves = 0;
+                    }
+                }
+                PlayerAction::Victory => {
+                    victory(objects, game, tcod);
+                    return
+                }
+            }
+
+            if !objects[PLAYER].alive {
+                game_over(objects, game, tcod);
+                return;
             }
 
             pinned = is_pinned(PLAYER, objects);
@@ -551,8 +900,10 @@ fn play_game(
         }
 
 
-        let mut remaining_attacks = objects[PLAYER].attacks(game);
-        let mut any_targets_in_range = has_valid_attack_targets(PLAYER, objects);
+        let mut remaining_attacks = objects.attacks(PLAYER) - attacks_spent;
+        let mut swings = 0;
+        let mut any_targets_in_range = !stunned
+            && (has_valid_attack_targets(PLAYER, objects) || can_shoot(objects, tcod));
 
         while any_targets_in_range && remaining_attacks > 0 {
             game.gutter_text(
@@ -574,7 +925,8 @@ fn play_game(
 
                     match index_of_target {
                         Some(target_index) => {
-                            attack(PLAYER, target_index, objects, game);
+                            attack(PLAYER, target_index, swings, objects, game);
+                            swings += 1;
                             remaining_attacks -= 1;
                         }
                         None => {
@@ -588,9 +940,28 @@ fn play_game(
                 PlayerAction::EndedMove => {
                     remaining_attacks = 0;
                 }
+                PlayerAction::Fire => {
+                    if fire(objects, game, tcod) {
+                        remaining_attacks -= 1;
+                    }
+                }
+                PlayerAction::Throw => {
+                    if throw(objects, game, tcod) {
+                        remaining_attacks -= 1;
+                    }
+                }
+                PlayerAction::Victory => {
+                    victory(objects, game, tcod);
+                    return
+                }
             }
 
-            any_targets_in_range = has_valid_attack_targets(PLAYER, objects);
+            if !objects[PLAYER].alive {
+                game_over(objects, game, tcod);
+                return;
+            }
+
+            any_targets_in_range = has_valid_attack_targets(PLAYER, objects) || can_shoot(objects, tcod);
 
             tcod.con.clear();
             display::render_all(
@@ -602,38 +973,65 @@ fn play_game(
         }
 
         if objects[PLAYER].alive {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
+            // Snapshot the ids first: a monster's turn can remove others.
+            for id in objects.ids() {
+                if objects.ais.contains(id) {
                     ai_take_turn(id, game, objects, &tcod.fov)
                 }
             }
+
+            tick_statuses(objects, game);
         }
     }
 }
 
+fn game_over(objects: &ObjectStore, game: &mut Game, tcod: &mut Tcod) {
+    delete_save();
+
+    let msg = format!(
+        "\nYou died on dungeon level {}.\n\nCharacter level: {}\nSeed: {}\n",
+        game.dungeon_level,
+        objects[PLAYER].level,
+        game.seed,
+    );
+    display::msgbox(&msg, END_SCREEN_WIDTH, &mut tcod.root);
+}
+
+fn victory(objects: &ObjectStore, game: &mut Game, tcod: &mut Tcod) {
+    delete_save();
+
+    let msg = format!(
+        "\nYou climb out of the Tombs of the Ancient Kings with the crown in your hands. \
+         The kingdom is yours!\n\nCharacter level: {}\nSeed: {}\n",
+        objects[PLAYER].level,
+        game.seed,
+    );
+    display::msgbox(&msg, END_SCREEN_WIDTH, &mut tcod.root);
+}
+
 fn is_pinned(
-    source_index: usize,
-    objects: &mut Vec<Object>,
+    source_id: EntityId,
+    objects: &mut ObjectStore,
 ) -> bool {
     // TODO: This should just check if there are any adjacent characters
     // as targets_in_range is just doing adjacency checking atm
 
-    has_valid_attack_targets(source_index, objects)
+    has_valid_attack_targets(source_id, objects)
 }
 
 fn has_valid_attack_targets(
-    source_index: usize,
-    objects: &mut Vec<Object>,
+    source_id: EntityId,
+    objects: &mut ObjectStore,
 ) -> bool {
-    let targets = targets_in_range(source_index, objects);
+    let targets = targets_in_range(source_id, objects);
     targets.len() > 0
 }
 
 fn targets_in_range(
-    source_index: usize,
-    objects: &mut Vec<Object>,
-) -> Vec<usize> {
-    let (source_x, source_y) = objects[source_index].pos();
+    source_id: EntityId,
+    objects: &mut ObjectStore,
+) -> Vec<EntityId> {
+    let (source_x, source_y) = objects[source_id].pos();
 
     let xs = source_x-1..source_x+2;
     let ys = source_y-1..source_y+2;
@@ -651,17 +1049,15 @@ fn targets_in_range(
             return Option::None;
         }
 
-        objects
-            .iter()
-            .position(|o| o.fighter.is_some() && o.x == x && o.y == y)
+        objects.entities_at(x, y).find(|&id| are_hostile(objects, source_id, id))
     }).collect()
 }
 
-fn load_game() -> Result<(Vec<Object>, Game), Box<Error>> {
+fn load_game() -> Result<(ObjectStore, Game), Box<Error>> {
     let mut json_save_state = String::new();
     let mut file = File::open("savegame")?;
     file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
+    let result = serde_json::from_str::<(ObjectStore, Game)>(&json_save_state)?;
 
     Ok(result)
 }
@@ -671,6 +1067,8 @@ fn main_menu(tcod: &mut Tcod) {
         .ok()
         .expect("Background image not found");
 
+    let mut seed = rng::random_seed();
+
     while !tcod.root.window_closed() {
         tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
 
@@ -689,14 +1087,22 @@ fn main_menu(tcod: &mut Tcod) {
             TextAlignment::Center,
             "KHANAGE Games"
         );
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT - 4,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            format!("Seed: {}", seed),
+        );
 
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "Continue last game", "Choose a seed", "Quit"];
         let choice = display::menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
-                let (mut objects, mut game) = new_game(tcod);
+                let (mut objects, mut game) = new_game(seed, tcod);
                 play_game(&mut objects, &mut game, tcod);
+                seed = rng::random_seed();
             }
             Some(1) => {
                 match load_game() {
@@ -711,6 +1117,11 @@ fn main_menu(tcod: &mut Tcod) {
                 }
             }
             Some(2) => {
+                if let Some(chosen) = display::number_prompt("Enter a seed:\n", 24, &mut tcod.root) {
+                    seed = chosen;
+                }
+            }
+            Some(3) => {
                 break;
             }
             _ => {}
@@ -718,7 +1129,12 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
-fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
+/// A finished run can't be continued.
+fn delete_save() {
+    let _ = std::fs::remove_file("savegame");
+}
+
+fn save_game(objects: &ObjectStore, game: &Game) -> Result<(), Box<Error>> {
     let save_data = serde_json::to_string(&(objects, game))?;
     let mut file = File::create("savegame")?;
     file.write_all(save_data.as_bytes())?;
@@ -726,7 +1142,41 @@ fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
     Ok(())
 }
 
+fn load_data() -> Result<(), String> {
+    // Items first, as monsters can start out carrying them.
+    items::init(ITEM_DATA)?;
+    affixes::init(AFFIX_DATA)?;
+    monsters::init(MONSTER_DATA)?;
+    vaults::init(VAULT_DIR)?;
+
+    Ok(())
+}
+
 fn main() {
+    if let Err(e) = load_data() {
+        eprintln!("Failed to load game data: {}", e);
+        std::process::exit(1);
+    }
+
+    // `roguelike --spawn-report [LEVEL]` prints the spawn tables for one
+    // level, or for the first ten if no level is given, and exits.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--spawn-report") {
+        let levels = match args.get(2).map(|level| level.parse::<u32>()) {
+            Some(Ok(level)) => level..level + 1,
+            Some(Err(_)) => {
+                eprintln!("Expected a dungeon level, got {}", args[2]);
+                std::process::exit(1);
+            }
+            None => 1..11,
+        };
+
+        for level in levels {
+            println!("{}", spawn_report(level));
+        }
+        return;
+    }
+
     let root = Root::initializer()
         .font("arial10x10.png", FontLayout::Tcod)
         .font_type(FontType::Greyscale)
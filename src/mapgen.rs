@@ -0,0 +1,538 @@
+use std::cmp;
+
+use rand::Rng;
+
+use crate::{
+    consts::*,
+    types::*,
+    rng::GameRng,
+    util::{Transition, from_dungeon_level},
+};
+
+/// A set of floor tiles that objects can be spawned on.
+pub type Region = Vec<(i32, i32)>;
+
+/// Everything a generator hands back to `make_map`: the tiles, the rooms
+/// (if the generator makes any), the regions objects can be spawned in, and
+/// where the player and the stairs go.
+pub struct GeneratedMap {
+    pub map: Map,
+    pub rooms: Vec<Rect>,
+    pub regions: Vec<Region>,
+    pub start: (i32, i32),
+    pub stairs: (i32, i32),
+}
+
+impl GeneratedMap {
+    /// Drop spawn tiles that have since become walls, doors or hazards.
+    pub fn prune_regions(&mut self) {
+        let map = &self.map;
+        for region in self.regions.iter_mut() {
+            region.retain(|&(x, y)| {
+                let tile = map[x as usize][y as usize];
+                !tile.blocked() && !tile.kind.is_hazard()
+            });
+        }
+        self.regions.retain(|region| !region.is_empty());
+    }
+}
+
+pub trait MapGenerator {
+    fn generate(&self, rng: &mut GameRng) -> GeneratedMap;
+}
+
+pub fn generator_for_level(level: u32) -> Box<dyn MapGenerator> {
+    match level % 3 {
+        1 => Box::new(RoomsAndCorridors {
+            max_rooms: MAX_ROOMS,
+        }),
+        2 => Box::new(Bsp {
+            min_leaf_size: BSP_MIN_LEAF_SIZE,
+        }),
+        _ => Box::new(Caves {
+            initial_wall_chance: CAVE_INITIAL_WALL_CHANCE,
+            smoothing_steps: CAVE_SMOOTHING_STEPS,
+        }),
+    }
+}
+
+/// Random non-overlapping rooms, each joined to the previous one by an
+/// L-shaped corridor.
+pub struct RoomsAndCorridors {
+    pub max_rooms: i32,
+}
+
+impl MapGenerator for RoomsAndCorridors {
+    fn generate(&self, rng: &mut GameRng) -> GeneratedMap {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut rooms: Vec<Rect> = vec![];
+
+        for _ in 0..self.max_rooms {
+            let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+            let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+
+            let x = rng.gen_range(0, MAP_WIDTH - w);
+            let y = rng.gen_range(0, MAP_HEIGHT - h);
+
+            let new_room = Rect::new(x, y, w, h);
+
+            let failed = rooms
+                .iter()
+                .any(|other_room| new_room.intersects_with(other_room));
+
+            if !failed {
+                create_room(new_room, &mut map);
+
+                if let Some(prev_room) = rooms.last() {
+                    connect(prev_room.center(), new_room.center(), &mut map, rng);
+                }
+
+                rooms.push(new_room);
+            }
+        }
+
+        GeneratedMap {
+            start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            regions: rooms.iter().map(Rect::tiles).collect(),
+            map,
+            rooms,
+        }
+    }
+}
+
+/// Binary space partitioning: the map is split in two again and again until
+/// the pieces are small, a room is carved into each piece, and sibling
+/// pieces are joined back up with corridors.
+pub struct Bsp {
+    pub min_leaf_size: i32,
+}
+
+impl Bsp {
+    /// Carve rooms into `area` and return one of them, so the caller can
+    /// connect this whole subtree to its sibling.
+    fn build(&self, area: Rect, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut GameRng) -> Rect {
+        let width = area.x2 - area.x1;
+        let height = area.y2 - area.y1;
+
+        let can_split_x = width >= self.min_leaf_size * 2;
+        let can_split_y = height >= self.min_leaf_size * 2;
+
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => return self.carve_leaf(area, map, rooms, rng),
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => {
+                if width * 4 > height * 5 {
+                    true
+                } else if height * 4 > width * 5 {
+                    false
+                } else {
+                    rng.gen()
+                }
+            }
+        };
+
+        let (first, second) = if split_x {
+            let split = area.x1 + rng.gen_range(self.min_leaf_size, width - self.min_leaf_size + 1);
+            (
+                Rect { x2: split, ..area },
+                Rect { x1: split, ..area },
+            )
+        } else {
+            let split = area.y1 + rng.gen_range(self.min_leaf_size, height - self.min_leaf_size + 1);
+            (
+                Rect { y2: split, ..area },
+                Rect { y1: split, ..area },
+            )
+        };
+
+        let first_room = self.build(first, map, rooms, rng);
+        let second_room = self.build(second, map, rooms, rng);
+
+        connect(first_room.center(), second_room.center(), map, rng);
+
+        if rng.gen() {
+            first_room
+        } else {
+            second_room
+        }
+    }
+
+    fn carve_leaf(&self, area: Rect, map: &mut Map, rooms: &mut Vec<Rect>, rng: &mut GameRng) -> Rect {
+        let max_w = cmp::min(area.x2 - area.x1, ROOM_MAX_SIZE);
+        let max_h = cmp::min(area.y2 - area.y1, ROOM_MAX_SIZE);
+
+        let w = rng.gen_range(cmp::min(ROOM_MIN_SIZE, max_w), max_w + 1);
+        let h = rng.gen_range(cmp::min(ROOM_MIN_SIZE, max_h), max_h + 1);
+
+        let x = rng.gen_range(area.x1, area.x2 - w + 1);
+        let y = rng.gen_range(area.y1, area.y2 - h + 1);
+
+        let room = Rect::new(x, y, w, h);
+        create_room(room, map);
+        rooms.push(room);
+
+        room
+    }
+}
+
+impl MapGenerator for Bsp {
+    fn generate(&self, rng: &mut GameRng) -> GeneratedMap {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut rooms = vec![];
+
+        let whole_map = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+        self.build(whole_map, &mut map, &mut rooms, rng);
+
+        // Rooms are pushed left-to-right / top-to-bottom, so the first and
+        // last ones sit in opposite corners of the map.
+        GeneratedMap {
+            start: rooms[0].center(),
+            stairs: rooms[rooms.len() - 1].center(),
+            regions: rooms.iter().map(Rect::tiles).collect(),
+            map,
+            rooms,
+        }
+    }
+}
+
+/// Cellular-automata caves: start from noise, smooth it into blobs, then
+/// throw away everything but the biggest connected cave.
+pub struct Caves {
+    pub initial_wall_chance: f32,
+    pub smoothing_steps: i32,
+}
+
+impl Caves {
+    fn noise(&self, rng: &mut GameRng) -> Map {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+        for x in 1..(MAP_WIDTH - 1) {
+            for y in 1..(MAP_HEIGHT - 1) {
+                if rng.gen::<f32>() >= self.initial_wall_chance {
+                    map[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+
+        map
+    }
+
+    fn smooth(map: &Map) -> Map {
+        let mut smoothed = map.clone();
+
+        for x in 1..(MAP_WIDTH - 1) {
+            for y in 1..(MAP_HEIGHT - 1) {
+                let walls = (-1..2)
+                    .flat_map(|dx| (-1..2).map(move |dy| (x + dx, y + dy)))
+                    .filter(|&(nx, ny)| map[nx as usize][ny as usize].kind == TileKind::Wall)
+                    .count();
+
+                smoothed[x as usize][y as usize] = if walls >= 5 {
+                    Tile::wall()
+                } else {
+                    Tile::empty()
+                };
+            }
+        }
+
+        smoothed
+    }
+}
+
+impl MapGenerator for Caves {
+    fn generate(&self, rng: &mut GameRng) -> GeneratedMap {
+        loop {
+            let mut map = self.noise(rng);
+
+            for _ in 0..self.smoothing_steps {
+                map = Caves::smooth(&map);
+            }
+
+            let cave = match floor_regions(&map).into_iter().max_by_key(|r| r.len()) {
+                Some(cave) => cave,
+                None => continue,
+            };
+
+            if (cave.len() as i32) < MAP_WIDTH * MAP_HEIGHT * CAVE_MIN_FLOOR_PERCENT / 100 {
+                continue;
+            }
+
+            for x in 0..MAP_WIDTH {
+                for y in 0..MAP_HEIGHT {
+                    map[x as usize][y as usize] = Tile::wall();
+                }
+            }
+            for &(x, y) in &cave {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+
+            let start = cave[rng.gen_range(0, cave.len())];
+
+            // The stairs go on the reachable tile furthest from the start, so
+            // the player has to cross the cave to find them.
+            let distances = flood_fill(&map, start);
+            let stairs = cave
+                .iter()
+                .cloned()
+                .max_by_key(|&(x, y)| distances[x as usize][y as usize])
+                .unwrap();
+
+            assert!(distances[stairs.0 as usize][stairs.1 as usize].is_some());
+
+            return GeneratedMap {
+                rooms: vec![],
+                regions: split_into_sectors(&cave, CAVE_SECTOR_SIZE),
+                start,
+                stairs,
+                map,
+            };
+        }
+    }
+}
+
+/// Dress a freshly generated level with doors, water and (deeper down)
+/// lava and chasms. Hazards are only kept if the stairs can still be reached
+/// without stepping on them.
+pub fn add_features(generated: &mut GeneratedMap, level: u32, rng: &mut GameRng) {
+    for room in generated.rooms.clone() {
+        add_doors(room, &mut generated.map, rng);
+    }
+
+    for _ in 0..WATER_POOLS_PER_LEVEL {
+        add_pool(generated, TileKind::ShallowWater, WATER_POOL_SIZE, rng);
+    }
+
+    let lava_pools = from_dungeon_level(
+        &[
+            Transition { level: 3, value: 1 },
+            Transition { level: 6, value: 2 },
+        ],
+        level,
+    );
+    for _ in 0..lava_pools {
+        add_pool(generated, TileKind::Lava, LAVA_POOL_SIZE, rng);
+    }
+
+    // There's nothing below the last level to fall into.
+    let chasms = if level < FINAL_LEVEL {
+        from_dungeon_level(&[Transition { level: 2, value: 1 }], level)
+    } else {
+        0
+    };
+    for _ in 0..chasms {
+        add_pool(generated, TileKind::Chasm, CHASM_SIZE, rng);
+    }
+
+    generated.prune_regions();
+}
+
+/// Put doors where corridors break through the walls of a room.
+fn add_doors(room: Rect, map: &mut Map, rng: &mut GameRng) {
+    let is_wall = |map: &Map, x: i32, y: i32| map[x as usize][y as usize].kind == TileKind::Wall;
+
+    let mut doorways = vec![];
+
+    for x in (room.x1 + 1)..room.x2 {
+        for &y in &[room.y1, room.y2] {
+            if !is_wall(map, x, y) && is_wall(map, x - 1, y) && is_wall(map, x + 1, y) {
+                doorways.push((x, y));
+            }
+        }
+    }
+
+    for y in (room.y1 + 1)..room.y2 {
+        for &x in &[room.x1, room.x2] {
+            if !is_wall(map, x, y) && is_wall(map, x, y - 1) && is_wall(map, x, y + 1) {
+                doorways.push((x, y));
+            }
+        }
+    }
+
+    for (x, y) in doorways {
+        if map[x as usize][y as usize].kind == TileKind::Floor && rng.gen::<f32>() < DOOR_CHANCE {
+            map[x as usize][y as usize] = Tile::new(TileKind::ClosedDoor);
+        }
+    }
+}
+
+/// Spread a blob of `kind` over the floor with a short random walk.
+fn add_pool(generated: &mut GeneratedMap, kind: TileKind, size: i32, rng: &mut GameRng) {
+    let floor = generated.regions.iter().flatten().cloned().collect::<Vec<_>>();
+    if floor.is_empty() {
+        return;
+    }
+
+    let mut map = generated.map.clone();
+    let (mut x, mut y) = floor[rng.gen_range(0, floor.len())];
+
+    for _ in 0..size {
+        let keep_clear = (x, y) == generated.start || (x, y) == generated.stairs;
+
+        if !keep_clear && map[x as usize][y as usize].kind == TileKind::Floor {
+            map[x as usize][y as usize] = Tile::new(kind);
+        }
+
+        x = (x + rng.gen_range(-1, 2)).clamp(1, MAP_WIDTH - 2);
+        y = (y + rng.gen_range(-1, 2)).clamp(1, MAP_HEIGHT - 2);
+    }
+
+    if kind.is_hazard() && floor_regions(&map).len() != 1 {
+        return;
+    }
+
+    generated.map = map;
+    generated.prune_regions();
+}
+
+/// Walking distance from `start` to every traversable tile, or `None` for
+/// tiles that can't be reached.
+pub fn flood_fill(map: &Map, start: (i32, i32)) -> Vec<Vec<Option<i32>>> {
+    let mut distances = vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut frontier = std::collections::VecDeque::new();
+
+    distances[start.0 as usize][start.1 as usize] = Some(0);
+    frontier.push_back(start);
+
+    while let Some((x, y)) = frontier.pop_front() {
+        let distance = distances[x as usize][y as usize].unwrap();
+
+        for &(dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+
+            let (ux, uy) = (nx as usize, ny as usize);
+            if map[ux][uy].kind.is_traversable() && distances[ux][uy].is_none() {
+                distances[ux][uy] = Some(distance + 1);
+                frontier.push_back((nx, ny));
+            }
+        }
+    }
+
+    distances
+}
+
+/// Every group of traversable tiles that are connected to each other.
+pub fn floor_regions(map: &Map) -> Vec<Region> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut regions = vec![];
+
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if !map[x as usize][y as usize].kind.is_traversable() || seen[x as usize][y as usize] {
+                continue;
+            }
+
+            let distances = flood_fill(map, (x, y));
+            let mut region = vec![];
+
+            for rx in 0..MAP_WIDTH {
+                for ry in 0..MAP_HEIGHT {
+                    if distances[rx as usize][ry as usize].is_some() {
+                        seen[rx as usize][ry as usize] = true;
+                        region.push((rx, ry));
+                    }
+                }
+            }
+
+            regions.push(region);
+        }
+    }
+
+    regions
+}
+
+/// Chop a big region into roughly room-sized pieces, so spawning in a cave
+/// ends up about as dense as spawning in rooms.
+fn split_into_sectors(region: &[(i32, i32)], sector_size: i32) -> Vec<Region> {
+    let sectors_x = (MAP_WIDTH + sector_size - 1) / sector_size;
+    let sectors_y = (MAP_HEIGHT + sector_size - 1) / sector_size;
+    let mut sectors = vec![vec![]; (sectors_x * sectors_y) as usize];
+
+    for &(x, y) in region {
+        let sector = (x / sector_size) * sectors_y + y / sector_size;
+        sectors[sector as usize].push((x, y));
+    }
+
+    sectors.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+#[derive(Clone,Copy,Debug)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+
+    /// The floor tiles `create_room` carves out of this rect.
+    pub fn tiles(&self) -> Region {
+        ((self.x1 + 1)..self.x2)
+            .flat_map(|x| ((self.y1 + 1)..self.y2).map(move |y| (x, y)))
+            .collect()
+    }
+}
+
+pub fn create_room(room: Rect, map: &mut Map) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+pub fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+pub fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+/// Join two points with an L-shaped corridor, picking the bend at random.
+pub fn connect(
+    (prev_x, prev_y): (i32, i32),
+    (new_x, new_y): (i32, i32),
+    map: &mut Map,
+    rng: &mut GameRng,
+) {
+    if rng.gen() {
+        create_h_tunnel(prev_x, new_x, prev_y, map);
+        create_v_tunnel(prev_y, new_y, new_x, map);
+    } else {
+        create_v_tunnel(prev_y, new_y, prev_x, map);
+        create_h_tunnel(prev_x, new_x, new_y, map);
+    }
+}
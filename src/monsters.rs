@@ -1,89 +1,233 @@
-use rand::distributions::Weighted;
+use std::sync::OnceLock;
+
+use rand::{distributions::{IndependentSample, Weighted, WeightedChoice}, Rng};
 use tcod::{
-    colors,
+    colors::Color,
 };
 use crate::{
     types::*,
     ai::Ai,
+    data,
+    entity::Bundle,
+    items::{self, ItemDef},
+    rng::GameRng,
+    combat::{Dice, Resistances},
+    faction::Faction,
     util::{Transition, from_dungeon_level},
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-pub enum Monster {
-    Orc,
-    Troll,
+/// Which kind of monster an entity is, so its `MonsterDef` can be found
+/// again, for instance to roll its loot when it dies.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Monster {
+    pub id: String,
+}
+
+impl Monster {
+    pub fn def(&self) -> Option<&'static MonsterDef> {
+        get(&self.id)
+    }
+}
+
+/// What a monster leaves behind besides its own gear. When it dies there is
+/// a `chance` in a hundred of one item off the weighted `table`, and every
+/// item in `always` is dropped regardless.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LootTable {
+    pub chance: Vec<Transition>,
+    pub table: Vec<(&'static ItemDef, Vec<Transition>)>,
+    pub always: Vec<&'static ItemDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LootData {
+    #[serde(default)]
+    chance: Vec<Transition>,
+    #[serde(default)]
+    table: Vec<LootEntryData>,
+    #[serde(default)]
+    always: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LootEntryData {
+    item: String,
+    weight: Vec<Transition>,
+}
+
+/// One kind of monster, as described in the monster data file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MonsterDef {
+    pub id: String,
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub damage: Dice,
+    pub resistances: Resistances,
+    pub faction: Faction,
+    pub movement: i32,
+    pub attacks: i32,
+    pub ai: Ai,
+    pub xp: i32,
+    pub gear: Vec<&'static ItemDef>,
+    pub loot: LootTable,
+    pub spawn: Vec<Transition>,
+}
+
+static MONSTERS: OnceLock<Vec<MonsterDef>> = OnceLock::new();
+
+pub fn init(path: &str) -> Result<(), String> {
+    let monsters = load(path)?;
+    MONSTERS.set(monsters).map_err(|_| "Monsters were already loaded".to_string())
+}
+
+pub fn all() -> &'static [MonsterDef] {
+    MONSTERS.get().map_or(&[], |monsters| monsters.as_slice())
+}
+
+pub fn get(id: &str) -> Option<&'static MonsterDef> {
+    all().iter().find(|monster| monster.id == id)
+}
+
+fn load(path: &str) -> Result<Vec<MonsterDef>, String> {
+    let mut monsters: Vec<MonsterDef> = vec![];
+
+    for mut entry in data::load_entries(path)? {
+        let monster = MonsterDef {
+            id: entry.field("id")?,
+            name: entry.field("name")?,
+            glyph: entry.field("glyph")?,
+            color: entry.field("color")?,
+            hp: entry.field("hp")?,
+            defense: entry.field("defense")?,
+            power: entry.field("power")?,
+            damage: entry.field("damage")?,
+            resistances: entry.optional_field("resistances")?.unwrap_or_default(),
+            faction: entry.field("faction")?,
+            movement: entry.field("movement")?,
+            attacks: entry.field("attacks")?,
+            ai: entry.field("ai")?,
+            xp: entry.field("xp")?,
+            gear: load_gear(&mut entry)?,
+            loot: load_loot(&mut entry)?,
+            spawn: entry.optional_field("spawn")?.unwrap_or_default(),
+        };
+
+        if monsters.iter().any(|other| other.id == monster.id) {
+            return Err(format!("{}: field `id`: `{}` is used twice", entry.context(), monster.id));
+        }
+
+        entry.finish()?;
+        monsters.push(monster);
+    }
+
+    Ok(monsters)
 }
 
-pub fn make_monster(x: i32, y: i32, monster: Monster) -> Object {
-    let mut monster = match monster {
-        Monster::Orc => {
-            let mut orc = Object::new(x, y, 'o', colors::DESATURATED_GREEN, "Orc", true);
-            orc.fighter = Some(Fighter{
-                base_max_hp: 10,
-                hp: 10,
-                base_defense: 0,
-                base_power: 3,
-                on_death: DeathCallback::Monster,
-                xp: 35,
-                base_movement: 4,
-                base_attacks: 1,
-            });
-            orc.ai = Some(Ai::Basic);
-
-            orc
-        },
-
-        Monster::Troll => {
-            let mut troll = Object::new(x, y, 'T', colors::DARKER_GREEN, "Troll", true);
-            troll.fighter = Some(Fighter {
-                base_max_hp: 16,
-                hp: 16,
-                base_defense: 1,
-                base_power: 4,
-                on_death: DeathCallback::Monster,
-                xp: 100,
-                base_movement: 3,
-                base_attacks: 1,
-            });
-            troll.ai = Some(Ai::Basic);
-
-            troll
-        },
+/// Monsters name the items they start with by id, so those must be loaded
+/// first.
+fn load_gear(entry: &mut data::Entry) -> Result<Vec<&'static ItemDef>, String> {
+    let ids: Vec<String> = entry.optional_field("gear")?.unwrap_or_default();
+
+    ids.iter()
+        .map(|id| {
+            items::get(id).ok_or_else(|| format!("{}: field `gear`: unknown item `{}`", entry.context(), id))
+        })
+        .collect()
+}
+
+fn load_loot(entry: &mut data::Entry) -> Result<LootTable, String> {
+    let loot: LootData = match entry.optional_field("loot")? {
+        Some(loot) => loot,
+        None => return Ok(LootTable::default()),
+    };
+
+    let find = |id: &str| {
+        items::get(id).ok_or_else(|| format!("{}: field `loot`: unknown item `{}`", entry.context(), id))
     };
 
-    monster.alive = true;
-    monster.always_visible = true;
+    Ok(LootTable {
+        chance: loot.chance,
+        table: loot
+            .table
+            .into_iter()
+            .map(|entry| Ok((find(&entry.item)?, entry.weight)))
+            .collect::<Result<_, String>>()?,
+        always: loot.always.iter().map(|id| find(id)).collect::<Result<_, _>>()?,
+    })
+}
+
+/// Roll what `monster` drops on dungeon level `level`.
+pub fn roll_loot(monster: &MonsterDef, level: u32, rng: &mut GameRng) -> Vec<&'static ItemDef> {
+    let loot = &monster.loot;
+    let mut drops = loot.always.clone();
+
+    let chance = from_dungeon_level(&loot.chance, level);
+    let table = &mut loot
+        .table
+        .iter()
+        .map(|&(item, ref weight)| Weighted {
+            weight: from_dungeon_level(weight, level),
+            item,
+        })
+        .filter(|entry| entry.weight > 0)
+        .collect::<Vec<_>>();
 
-    monster
+    if !table.is_empty() && rng.gen_range(0, 100) < chance {
+        drops.push(WeightedChoice::new(table).ind_sample(rng));
+    }
+
+    drops
 }
 
-pub fn monster_table_for_level(level: u32) -> Vec<Weighted<Monster>> {
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition {
-                level: 3,
-                value: 15,
-            },
-            Transition {
-                level: 5,
-                value: 30,
-            },
-            Transition {
-                level: 7,
-                value: 60,
-            },
-        ],
-        level,
-    );
-
-    vec![
-        Weighted {
-            weight: 80,
-            item: Monster::Orc,
-        },
-        Weighted {
-            weight: troll_chance,
-            item: Monster::Troll,
+pub fn make_monster(x: i32, y: i32, monster: &MonsterDef) -> Bundle {
+    let mut object = Object::new(x, y, monster.glyph, monster.color, &monster.name, true);
+    object.alive = true;
+    object.always_visible = true;
+
+    let mut bundle = Bundle::from(object);
+
+    bundle.fighter = Some(Fighter {
+        base_max_hp: monster.hp,
+        hp: monster.hp,
+        base_defense: monster.defense,
+        base_power: monster.power,
+        damage: monster.damage,
+        resistances: monster.resistances,
+        faction: monster.faction,
+        on_death: DeathCallback::Monster,
+        xp: monster.xp,
+        base_movement: monster.movement,
+        base_attacks: monster.attacks,
+    });
+    bundle.ai = Some(monster.ai.clone());
+    bundle.monster = Some(Monster {
+        id: monster.id.clone(),
+    });
+
+    if !monster.gear.is_empty() {
+        let mut inventory = vec![];
+        for &item in &monster.gear {
+            items::give_item(&mut inventory, items::make_item(x, y, item));
         }
-    ]
+        bundle.inventory = Some(inventory);
+    }
+
+    bundle
+}
+
+pub fn monster_table_for_level(level: u32) -> Vec<Weighted<&'static MonsterDef>> {
+    all()
+        .iter()
+        .map(|monster| Weighted {
+            weight: from_dungeon_level(&monster.spawn, level),
+            item: monster,
+        })
+        .filter(|entry| entry.weight > 0)
+        .collect()
 }
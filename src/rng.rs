@@ -0,0 +1,40 @@
+use rand::Rng;
+
+/// The single source of randomness for a run.
+///
+/// Everything that rolls dice during play goes through the `GameRng` owned by
+/// `Game`, so the same seed always produces the same dungeon. The state is a
+/// plain `u64`, which means it is saved alongside the rest of the game and a
+/// loaded game carries on with the same sequence.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GameRng {
+    state: u64,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng { state: seed }
+    }
+}
+
+impl Rng for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    // SplitMix64, which is small, fast and fine with any seed (including 0).
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Pick a fresh seed for a new run. This is the only place allowed to reach
+/// for the thread RNG.
+pub fn random_seed() -> u64 {
+    rand::random()
+}
@@ -0,0 +1,173 @@
+use tcod::colors;
+
+use crate::{
+    consts::*,
+    types::*,
+    logging::*,
+    entity::{EntityId, ObjectStore},
+    combat::{self, DamageType, Source},
+};
+
+/// A timed condition on a creature. Most of them just shift its stats for
+/// as long as they last; poison and regeneration also do something every
+/// turn, and stun and confusion take over whatever the creature was about to
+/// do.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Status {
+    Poisoned,
+    Regenerating,
+    Hasted,
+    Slowed,
+    Stunned,
+    Weakened,
+    Confused,
+}
+
+impl Status {
+    pub fn power_bonus(self) -> i32 {
+        match self {
+            Status::Weakened => -WEAKNESS_POWER_PENALTY,
+            _ => 0,
+        }
+    }
+
+    pub fn defense_bonus(self) -> i32 {
+        match self {
+            Status::Stunned => -STUN_DEFENSE_PENALTY,
+            _ => 0,
+        }
+    }
+
+    pub fn movement_bonus(self) -> i32 {
+        match self {
+            Status::Hasted => HASTE_MOVEMENT_BONUS,
+            Status::Slowed => -SLOW_MOVEMENT_PENALTY,
+            _ => 0,
+        }
+    }
+
+    pub fn attacks_bonus(self) -> i32 {
+        match self {
+            Status::Hasted => HASTE_ATTACKS_BONUS,
+            Status::Slowed => -SLOW_ATTACKS_PENALTY,
+            _ => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Status::Poisoned => write!(f, "poisoned"),
+            Status::Regenerating => write!(f, "regenerating"),
+            Status::Hasted => write!(f, "hasted"),
+            Status::Slowed => write!(f, "slowed"),
+            Status::Stunned => write!(f, "stunned"),
+            Status::Weakened => write!(f, "weakened"),
+            Status::Confused => write!(f, "confused"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StatusEffect {
+    pub status: Status,
+    pub turns: i32,
+    /// Who is to thank if this kills the creature.
+    #[serde(default)]
+    pub applied_by: Option<EntityId>,
+}
+
+/// Every status a creature is under, at most one of each kind.
+pub type StatusEffects = Vec<StatusEffect>;
+
+pub fn has_status(objects: &ObjectStore, id: EntityId, status: Status) -> bool {
+    objects
+        .statuses
+        .get(id)
+        .is_some_and(|effects| effects.iter().any(|effect| effect.status == status))
+}
+
+/// Put `id` under `status` for `turns` turns. Catching the same thing again
+/// doesn't stack, it just tops up the time left and passes the credit to
+/// whoever did it last.
+pub fn apply_status(
+    objects: &mut ObjectStore,
+    id: EntityId,
+    status: Status,
+    turns: i32,
+    applied_by: Option<EntityId>,
+) {
+    if !objects.statuses.contains(id) {
+        objects.statuses.insert(id, vec![]);
+    }
+
+    let effects = objects.statuses.get_mut(id).unwrap();
+
+    match effects.iter_mut().find(|effect| effect.status == status) {
+        Some(effect) => {
+            effect.turns = effect.turns.max(turns);
+            effect.applied_by = applied_by;
+        }
+        None => effects.push(StatusEffect { status, turns, applied_by }),
+    }
+}
+
+/// Run one turn of every status on the level: poison bites, regeneration
+/// heals, and anything that has run out wears off.
+pub fn tick_statuses(objects: &mut ObjectStore, game: &mut Game) {
+    for id in objects.ids() {
+        let effects = match objects.statuses.get(id) {
+            Some(effects) => effects.clone(),
+            None => continue,
+        };
+
+        for effect in &effects {
+            if !objects[id].alive {
+                break;
+            }
+
+            match effect.status {
+                Status::Poisoned => {
+                    let source = Source::Status { status: Status::Poisoned, applied_by: effect.applied_by };
+                    let events = combat::resolve_damage(id, POISON_DAMAGE, DamageType::Poison, source, objects, game);
+                    combat::publish(events, game);
+                }
+                Status::Regenerating => {
+                    let source = Source::Status { status: Status::Regenerating, applied_by: effect.applied_by };
+                    let events = combat::resolve_heal(id, REGENERATION_AMOUNT, source, objects);
+                    combat::publish(events, game);
+                }
+                _ => {}
+            }
+        }
+
+        let expired: Vec<Status> = effects
+            .iter()
+            .filter(|effect| effect.turns <= 1)
+            .map(|effect| effect.status)
+            .collect();
+
+        if let Some(effects) = objects.statuses.get_mut(id) {
+            for effect in effects.iter_mut() {
+                effect.turns -= 1;
+            }
+            effects.retain(|effect| effect.turns > 0);
+
+            if effects.is_empty() {
+                objects.statuses.remove(id);
+            }
+        }
+
+        if objects[id].alive {
+            for status in expired {
+                let message = if id == PLAYER {
+                    format!("You are no longer {}.", status)
+                } else {
+                    format!("The {} is no longer {}.", objects[id].name, status)
+                };
+                game.log.gutter_text(message, colors::LIGHT_GREY);
+            }
+        }
+    }
+}
@@ -7,14 +7,22 @@ use tcod::{
     input::{Mouse},
 };
 
+use std::collections::HashMap;
+
 use rand::Rng;
 
 use crate::{
     consts::*,
     util::*,
     logging::MessageLog,
-    items::{Item, Equipment},
+    items::{self, Item, Equipment, Slot},
+    monsters::{self, Monster},
+    status::Status,
+    combat::{CombatStats, DamageType, Dice, Resistances},
     ai::Ai,
+    rng::GameRng,
+    faction::Faction,
+    entity::{Bundle, EntityId, ObjectStore},
     closest_monster,
     target_tile,
 };
@@ -22,6 +30,8 @@ use crate::{
 pub type Map = Vec<Vec<Tile>>;
 pub type Messages = Vec<(String, Color)>;
 
+/// What every entity has: a name, a place on the map and a glyph. Anything
+/// more is a component in the `ObjectStore`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Object {
     pub name: String,
@@ -37,14 +47,7 @@ pub struct Object {
     pub blocks: bool,
     pub alive: bool,
 
-    pub fighter: Option<Fighter>,
-    pub ai: Option<Ai>,
-
-    pub item: Option<Item>,
-
     pub always_visible: bool,
-
-    pub equipment: Option<Equipment>,
 }
 
 impl Object {
@@ -58,11 +61,7 @@ impl Object {
             name: name.to_string(),
             blocks: blocks,
             alive: false,
-            fighter: Option::None,
-            ai: Option::None,
-            item: Option::None,
             always_visible: false,
-            equipment: None,
         }
     }
 
@@ -75,11 +74,6 @@ impl Object {
         (self.x, self.y)
     }
 
-    pub fn set_pos(&mut self, x: i32, y: i32) {
-        self.x = x;
-        self.y = y;
-    }
-
     pub fn distance_to(&self, other: &Object) -> f32 {
         let dx = other.x - self.x;
         let dy = other.y - self.y;
@@ -90,18 +84,20 @@ impl Object {
     pub fn distance(&self, x: i32, y: i32) -> f32 {
         (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
     }
+}
 
-    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
-        if let Some(fighter) = self.fighter.as_mut() {
+impl ObjectStore {
+    pub fn take_damage(&mut self, id: EntityId, damage: i32, game: &mut Game) -> Option<i32> {
+        if let Some(fighter) = self.fighters.get_mut(id) {
             if damage > 0 {
                 fighter.hp -= damage;
             }
         }
 
-        if let Some(fighter) = self.fighter {
+        if let Some(&fighter) = self.fighters.get(id) {
             if fighter.hp <= 0 {
-                self.alive = false;
-                fighter.on_death.callback(self, game);
+                self[id].alive = false;
+                fighter.on_death.callback(id, self, game);
                 return Some(fighter.xp)
             }
         }
@@ -109,52 +105,165 @@ impl Object {
         None
     }
 
-    pub fn attack(
-        &mut self,
-        target_index: usize,
-        objects: &mut [Object],
-        game: &mut Game,
-    ) {
-        let target = &mut objects[target_index];
-        let power = self.power(game);
-        let defense = target.defense(game);
-
-        let damage = power - defense;
+    pub fn heal(&mut self, id: EntityId, amount: i32) {
+        let max_hp = self.max_hp(id);
+        if let Some(fighter) = self.fighters.get_mut(id) {
+            fighter.hp += amount;
 
-        if damage > 0 {
-            game.log.gutter_text(
-                format!("{} attacks {} for {} hit points.", self.name, target.name, damage),
-                colors::WHITE,
-            );
-            if let Some(xp) = target.take_damage(damage, game) {
-                self.fighter.as_mut().unwrap().xp += xp;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
             }
+        }
+    }
+
+    pub fn get_all_equipped(&self, id: EntityId) -> Vec<Equipment> {
+        self.inventories
+            .get(id)
+            .into_iter()
+            .flatten()
+            .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+            .map(|item| item.equipment.unwrap())
+            .collect()
+    }
+
+    pub fn power(&self, id: EntityId) -> i32 {
+        self.get_with_bonus(
+            id,
+            |f| f.base_power,
+            |e| e.power_bonus,
+            Status::power_bonus,
+        )
+    }
+
+    pub fn defense(&self, id: EntityId) -> i32 {
+        self.get_with_bonus(
+            id,
+            |f| f.base_defense,
+            |e| e.defense_bonus,
+            Status::defense_bonus,
+        )
+    }
+
+    pub fn movement(&self, id: EntityId) -> i32 {
+        self.get_with_bonus(
+            id,
+            |f| f.base_movement,
+            |e| e.movement_bonus,
+            Status::movement_bonus,
+        )
+    }
+
+    pub fn max_hp(&self, id: EntityId) -> i32 {
+        self.get_with_bonus(
+            id,
+            |f| f.base_max_hp,
+            |e| e.max_hp_bonus,
+            |_| 0,
+        )
+    }
+
+    /// Can drop to nothing for a creature that is slowed down enough.
+    pub fn attacks(&self, id: EntityId) -> i32 {
+        let attacks = self.get_with_bonus(
+            id,
+            |f| f.base_attacks,
+            |e| e.attacks_bonus,
+            Status::attacks_bonus,
+        );
+
+        (attacks + self.dual_wield_bonus(id)).max(0)
+    }
+
+    /// A weapon in each hand is good for an extra swing.
+    fn dual_wield_bonus(&self, id: EntityId) -> i32 {
+        let weapons = self
+            .get_all_equipped(id)
+            .iter()
+            .filter(|e| !e.two_handed && e.range == 0 && e.damage.is_some())
+            .count();
+
+        if weapons >= 2 {
+            DUAL_WIELD_ATTACKS_BONUS
         } else {
-            game.log.gutter_text(
-                format!(
-                    "{} attacks {} but it has no effect!",
-                    self.name, target.name
-                ),
-                colors::WHITE,
-            );
+            0
         }
     }
 
-    pub fn heal(&mut self, amount: i32, game: &Game) {
-        let max_hp = self.max_hp(game);
-        if let Some(ref mut fighter) = self.fighter {
-            fighter.hp += amount;
+    pub fn resistance(&self, id: EntityId, kind: DamageType) -> i32 {
+        self.get_with_bonus(
+            id,
+            |f| f.resistances.get(kind),
+            |e| e.resistances.get(kind),
+            |_| 0,
+        )
+    }
 
-            if fighter.hp > max_hp {
-                fighter.hp = max_hp;
-            }
+    /// The bow or the like that `id` has ready, if any.
+    pub fn ranged_weapon(&self, id: EntityId) -> Option<Equipment> {
+        self.get_all_equipped(id)
+            .into_iter()
+            .find(|equipment| equipment.range > 0 && equipment.ammo.is_some())
+    }
+
+    /// The dice of each melee weapon `id` is wielding, main hand first, or
+    /// of its bare hands if it has none.
+    pub fn weapon_dice(&self, id: EntityId) -> Vec<Dice> {
+        let mut weapons: Vec<Equipment> = self
+            .get_all_equipped(id)
+            .into_iter()
+            .filter(|equipment| equipment.range == 0 && equipment.damage.is_some())
+            .collect();
+        weapons.sort_by_key(|equipment| equipment.slot != Slot::RightHand);
+
+        let dice: Vec<Dice> = weapons.iter().filter_map(|equipment| equipment.damage).collect();
+        if dice.is_empty() {
+            vec![self.fighters.get(id).map_or(Dice::new(1, 1), |fighter| fighter.damage)]
+        } else {
+            dice
         }
     }
 
+    /// What `id` rolls on its `swing`th attack of the turn: the weapons
+    /// take it in turns, starting with the main hand.
+    pub fn damage_dice(&self, id: EntityId, swing: usize) -> Dice {
+        let dice = self.weapon_dice(id);
+        dice[swing % dice.len()]
+    }
+
+    fn get_with_bonus<BaseGet, BonusGet, StatusGet>(
+        &self,
+        id: EntityId,
+        base: BaseGet,
+        bonus: BonusGet,
+        status_bonus: StatusGet,
+    ) -> i32 where
+        BaseGet: FnOnce(&Fighter) -> i32,
+        BonusGet: FnMut(&Equipment) -> i32,
+        StatusGet: Fn(Status) -> i32,
+    {
+        let base: i32 = self.fighters.get(id).map_or(0, base);
+        let bonus: i32 = self
+            .get_all_equipped(id)
+            .iter()
+            .map(bonus)
+            .sum();
+        let status_bonus: i32 = self
+            .statuses
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(|effect| status_bonus(effect.status))
+            .sum();
+
+        base + bonus + status_bonus
+    }
+}
+
+impl Bundle {
     pub fn equip(&mut self, log: &mut Vec<(String,Color)>) {
         if self.item.is_none() {
             log.gutter_text(
-                format!("Can't equip {:?} because it's not an Item.", self),
+                format!("Can't equip {:?} because it's not an Item.", self.object),
                 colors::RED,
             );
 
@@ -165,13 +274,13 @@ impl Object {
             if !equipment.equipped {
                 equipment.equipped = true;
                 log.gutter_text(
-                    format!("Equipped {} on {}.", self.name, equipment.slot),
+                    format!("Equipped {} on {}.", self.object.name, equipment.worn_on()),
                     colors::LIGHT_GREEN,
                 );
             }
         } else {
             log.gutter_text(
-                format!("Can't equip {:?} because it's not an Equipment.", self),
+                format!("Can't equip {:?} because it's not an Equipment.", self.object),
                 colors::RED,
             );
         }
@@ -180,7 +289,7 @@ impl Object {
     pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
         if self.item.is_none() {
             log.gutter_text(
-                format!("Can't dequip {:?} because it's not an Item.", self),
+                format!("Can't dequip {:?} because it's not an Item.", self.object),
                 colors::RED,
             );
             return;
@@ -190,126 +299,37 @@ impl Object {
             if equipment.equipped {
                 equipment.equipped = false;
                 log.gutter_text(
-                    format!("Dequipped {} from {}", self.name, equipment.slot),
+                    format!("Dequipped {} from {}", self.object.name, equipment.worn_on()),
                     colors::LIGHT_YELLOW,
                 );
             }
         } else {
             log.gutter_text(
-                format!("Can't dequip {:?} because it's not an Equipment.", self),
+                format!("Can't dequip {:?} because it's not an Equipment.", self.object),
                 colors::RED,
             );
         }
     }
-
-    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
-        if self.name == "player" {
-            game.inventory
-                .iter()
-                .filter(|item| item.equipment.map_or(false, |e| e.equipped))
-                .map(|item| item.equipment.unwrap())
-                .collect()
-        } else {
-            vec![]
-        }
-    }
-
-    pub fn power(&self, game: &Game) -> i32 {
-        self.get_with_bonus(
-            game,
-            |f| f.base_power,
-            |e| e.power_bonus,
-        )
-    }
-
-    pub fn defense(&self, game: &Game) -> i32 {
-        self.get_with_bonus(
-            game,
-            |f| f.base_defense,
-            |e| e.defense_bonus,
-        )
-    }
-
-    pub fn movement(&self, game: &Game) -> i32 {
-        self.get_with_bonus(
-            game,
-            |f| f.base_movement,
-            |e| e.movement_bonus,
-        )
-    }
-
-    pub fn max_hp(&self, game: &Game) -> i32 {
-        self.get_with_bonus(
-            game,
-            |f| f.base_max_hp,
-            |e| e.max_hp_bonus
-        )
-    }
-
-    pub fn attacks(&self, game: &Game) -> i32 {
-        self.get_with_bonus(
-            game,
-            |f| f.base_attacks,
-            |e| e.attacks_bonus,
-        )
-    }
-
-    fn get_with_bonus<BaseGet, BonusGet>(
-        &self,
-        game: &Game,
-        base: BaseGet,
-        bonus: BonusGet
-    ) -> i32 where
-        BaseGet: FnOnce(Fighter) -> i32,
-        BonusGet: FnMut(&Equipment) -> i32,
-    {
-        let base: i32 = self.fighter.map_or(0, base);
-        let bonus: i32 = self
-            .get_all_equipped(game)
-            .iter()
-            .map(bonus)
-            .sum();
-
-        base + bonus
-    }
-}
-
-pub fn attack(
-    attacker_index: usize,
-    target_index: usize,
-    objects: &mut [Object],
-    game: &mut Game,
-) {
-    let power = objects[attacker_index].power(game);
-    let defense = objects[target_index].defense(game);
-
-    let damage = power - defense;
-
-    if damage > 0 {
-        game.log.gutter_text(
-            format!("{} attacks {} for {} hit points.", objects[attacker_index].name, objects[target_index].name, damage),
-            colors::WHITE,
-        );
-        if let Some(xp) = objects[target_index].take_damage(damage, game) {
-            objects[attacker_index].fighter.as_mut().unwrap().xp += xp;
-        }
-    } else {
-        game.log.gutter_text(
-            format!(
-                "{} attacks {} but it has no effect!",
-                objects[attacker_index].name, objects[target_index].name
-            ),
-            colors::WHITE,
-        );
-    }
 }
 
 #[derive(Deserialize,Serialize)]
 pub struct Game {
     pub map: Map,
     pub log: Messages,
-    pub inventory: Vec<Object>,
     pub dungeon_level: u32,
+    pub levels: HashMap<u32, Level>,
+    pub seed: u64,
+    pub rng: GameRng,
+    #[serde(default)]
+    pub stats: CombatStats,
+}
+
+/// A level the player isn't on right now, kept exactly as they left it.
+/// `objects` holds everything on the level except the player.
+#[derive(Deserialize,Serialize)]
+pub struct Level {
+    pub map: Map,
+    pub objects: ObjectStore,
 }
 
 pub struct Tcod {
@@ -326,29 +346,119 @@ pub enum PlayerAction {
     Exit,
     Move(i32, i32),
     EndedMove,
+    Fire,
+    Throw,
+    Victory,
 }
 
 #[derive(Clone,Copy,Debug,Deserialize,Serialize)]
 pub struct Tile {
-    pub blocked: bool,
-    pub block_sight: bool,
+    pub kind: TileKind,
     pub explored: bool,
 }
 
 impl Tile {
-    pub fn empty() -> Self {
+    pub fn new(kind: TileKind) -> Self {
         Tile {
-            blocked: false,
-            block_sight: false,
+            kind,
             explored: false,
         }
     }
 
+    pub fn empty() -> Self {
+        Tile::new(TileKind::Floor)
+    }
+
     pub fn wall() -> Self {
-        Tile {
-            blocked: true,
-            block_sight: true,
-            explored: false,
+        Tile::new(TileKind::Wall)
+    }
+
+    pub fn blocked(&self) -> bool {
+        self.kind.blocks_movement()
+    }
+
+    pub fn block_sight(&self) -> bool {
+        self.kind.blocks_sight()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum TileKind {
+    Wall,
+    Floor,
+    ClosedDoor,
+    OpenDoor,
+    ShallowWater,
+    Lava,
+    Chasm,
+}
+
+/// What happens to whoever steps onto a tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileEffect {
+    Nothing,
+    Burn(i32),
+    Fall,
+}
+
+impl TileKind {
+    pub fn blocks_movement(self) -> bool {
+        matches!(self, TileKind::Wall | TileKind::ClosedDoor)
+    }
+
+    pub fn blocks_sight(self) -> bool {
+        matches!(self, TileKind::Wall | TileKind::ClosedDoor)
+    }
+
+    /// Whether a path may go through this tile: closed doors can be opened,
+    /// but nobody should have to walk through lava or jump down a chasm.
+    pub fn is_traversable(self) -> bool {
+        !matches!(self, TileKind::Wall | TileKind::Lava | TileKind::Chasm)
+    }
+
+    pub fn is_hazard(self) -> bool {
+        self.on_enter() != TileEffect::Nothing
+    }
+
+    pub fn movement_cost(self) -> i32 {
+        match self {
+            TileKind::ShallowWater => SHALLOW_WATER_MOVEMENT_COST,
+            _ => 1,
+        }
+    }
+
+    pub fn on_enter(self) -> TileEffect {
+        match self {
+            TileKind::Lava => TileEffect::Burn(LAVA_DAMAGE),
+            TileKind::Chasm => TileEffect::Fall,
+            _ => TileEffect::Nothing,
+        }
+    }
+
+    pub fn glyph(self) -> Option<char> {
+        match self {
+            TileKind::ClosedDoor => Some('+'),
+            TileKind::OpenDoor => Some('\''),
+            TileKind::ShallowWater | TileKind::Lava => Some('~'),
+            _ => None,
+        }
+    }
+
+    /// The (background, glyph) colours for this kind of tile.
+    pub fn colors(self, visible: bool) -> (Color, Color) {
+        match (self, visible) {
+            (TileKind::Wall, false) => (COLOR_DARK_WALL, COLOR_DARK_WALL),
+            (TileKind::Wall, true) => (COLOR_LIGHT_WALL, COLOR_LIGHT_WALL),
+            (TileKind::Floor, false) => (COLOR_DARK_GROUND, COLOR_DARK_GROUND),
+            (TileKind::Floor, true) => (COLOR_LIGHT_GROUND, COLOR_LIGHT_GROUND),
+            (TileKind::ClosedDoor, false) | (TileKind::OpenDoor, false) => (COLOR_DARK_GROUND, COLOR_DARK_DOOR),
+            (TileKind::ClosedDoor, true) | (TileKind::OpenDoor, true) => (COLOR_LIGHT_GROUND, COLOR_LIGHT_DOOR),
+            (TileKind::ShallowWater, false) => (COLOR_DARK_WATER, colors::DARK_SKY),
+            (TileKind::ShallowWater, true) => (COLOR_LIGHT_WATER, colors::SKY),
+            (TileKind::Lava, false) => (COLOR_DARK_LAVA, colors::DARK_ORANGE),
+            (TileKind::Lava, true) => (COLOR_LIGHT_LAVA, colors::YELLOW),
+            (TileKind::Chasm, false) => (COLOR_DARK_CHASM, COLOR_DARK_CHASM),
+            (TileKind::Chasm, true) => (COLOR_LIGHT_CHASM, COLOR_LIGHT_CHASM),
         }
     }
 }
@@ -362,6 +472,11 @@ pub struct Fighter {
     pub base_max_hp: i32,
     pub base_defense: i32,
     pub base_power: i32,
+    /// What it hits for without a weapon.
+    pub damage: Dice,
+    #[serde(default)]
+    pub resistances: Resistances,
+    pub faction: Faction,
     pub on_death: DeathCallback,
 
     pub xp: i32,
@@ -374,38 +489,60 @@ pub enum DeathCallback {
 }
 
 impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
+    fn callback(self, id: EntityId, objects: &mut ObjectStore, game: &mut Game) {
         use DeathCallback::*;
 
-        let callback: fn(&mut Object, &mut Game) = match self {
+        let callback: fn(EntityId, &mut ObjectStore, &mut Game) = match self {
             Player => player_death,
             Monster => monster_death,
         };
 
-        callback(object, game);
+        callback(id, objects, game);
     }
 }
 
-pub fn player_death(player: &mut Object, game: &mut Game) {
-    game.log.gutter_text(
-        "You died!",
-        colors::RED,
-    );
-
+pub fn player_death(id: EntityId, objects: &mut ObjectStore, _game: &mut Game) {
+    let player = &mut objects[id];
     player.char = '%';
     player.color = colors::DARK_RED;
 }
 
-pub fn monster_death(monster: &mut Object, game: &mut Game) {
-    game.log.gutter_text(
-        format!("{} is dead! You gain {} experience points.", monster.name, monster.fighter.unwrap().xp),
-        colors::ORANGE,
-    );
+pub fn monster_death(id: EntityId, objects: &mut ObjectStore, game: &mut Game) {
+    objects.fighters.remove(id);
+    objects.ais.remove(id);
+    objects.statuses.remove(id);
+    drop_inventory(id, objects);
+    drop_loot(id, objects, game);
 
+    let monster = &mut objects[id];
     monster.char = '%';
     monster.color = colors::DARK_RED;
     monster.blocks = false;
-    monster.fighter = None;
-    monster.ai = None;
     monster.name = format!("remains of {}", monster.name);
 }
+
+/// Everything a creature was carrying falls to the floor where it stood.
+fn drop_inventory(id: EntityId, objects: &mut ObjectStore) {
+    let (x, y) = objects[id].pos();
+
+    for mut item in objects.inventories.remove(id).unwrap_or_default() {
+        if let Some(ref mut equipment) = item.equipment {
+            equipment.equipped = false;
+        }
+
+        let item_id = objects.insert(item);
+        objects.set_pos(item_id, x, y);
+    }
+}
+
+fn drop_loot(id: EntityId, objects: &mut ObjectStore, game: &mut Game) {
+    let def = match objects.monsters.get(id).and_then(Monster::def) {
+        Some(def) => def,
+        None => return,
+    };
+
+    let (x, y) = objects[id].pos();
+    for item in monsters::roll_loot(def, game.dungeon_level, &mut game.rng) {
+        objects.insert(items::roll_item(x, y, item, game.dungeon_level, &mut game.rng));
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+use crate::{
+    consts::*,
+    types::*,
+    items::{self, ItemDef},
+    monsters::{self, MonsterDef},
+    mapgen::{GeneratedMap, floor_regions},
+    rng::GameRng,
+};
+
+/// A hand-drawn room, loaded from a text file in `VAULT_DIR`.
+///
+/// A vault file starts with a header, then a blank line, then the ASCII art:
+///
+/// ```text
+/// name: Orc barracks
+/// o: monster orc
+/// !: item healing_potion
+///
+/// #######
+/// #o.!.o#
+/// ###.###
+/// ```
+///
+/// In the art `#` is wall, `.` is floor, `+` is a door, `~` is shallow water
+/// and a space leaves whatever the generator put there. Any other character
+/// must be given in the header as either `monster <id>` or `item <id>`,
+/// using ids from the data files, and is floor with that thing spawned on
+/// it.
+#[derive(Clone, Debug)]
+pub struct Vault {
+    pub name: String,
+    pub cells: Vec<Vec<Cell>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cell {
+    Untouched,
+    Wall,
+    Floor,
+    Door,
+    Water,
+    Spawn(Spawn),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spawn {
+    Monster(&'static MonsterDef),
+    Item(&'static ItemDef),
+}
+
+impl Vault {
+    pub fn width(&self) -> i32 {
+        self.cells.iter().map(|row| row.len()).max().unwrap_or(0) as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.cells.len() as i32
+    }
+
+    fn cells_at(&self, x: i32, y: i32) -> impl Iterator<Item = (i32, i32, Cell)> + '_ {
+        self.cells.iter().enumerate().flat_map(move |(dy, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|&(_, cell)| *cell != Cell::Untouched)
+                .map(move |(dx, &cell)| (x + dx as i32, y + dy as i32, cell))
+        })
+    }
+}
+
+static VAULTS: OnceLock<Vec<Vault>> = OnceLock::new();
+
+/// Load every `.txt` file in `dir`. A missing directory just means there
+/// are no vaults; a broken file is an error naming the file and line.
+pub fn init(dir: &str) -> Result<(), String> {
+    let vaults = load_all(Path::new(dir))?;
+    VAULTS.set(vaults).map_err(|_| "Vaults were already loaded".to_string())
+}
+
+pub fn all() -> &'static [Vault] {
+    VAULTS.get().map_or(&[], |vaults| vaults.as_slice())
+}
+
+fn load_all(dir: &Path) -> Result<Vec<Vault>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(vec![]),
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("txt"))
+        .collect::<Vec<_>>();
+
+    // Sorted so the same seed picks the same vaults on every machine.
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+        })
+        .collect()
+}
+
+fn parse(text: &str) -> Result<Vault, String> {
+    let mut name = None;
+    let mut legend = vec![];
+    let mut lines = text.lines().enumerate();
+
+    for (line_no, line) in &mut lines {
+        if line.trim().is_empty() {
+            break;
+        }
+
+        let (key, value) = match line.find(':') {
+            Some(colon) => (&line[..colon], line[colon + 1..].trim()),
+            None => return Err(format!("{}: expected `key: value`", line_no + 1)),
+        };
+
+        if key == "name" {
+            name = Some(value.to_string());
+            continue;
+        }
+
+        let mut key_chars = key.chars();
+        let symbol = match (key_chars.next(), key_chars.next()) {
+            (Some(symbol), None) => symbol,
+            _ => return Err(format!("{}: unknown header `{}`", line_no + 1, key)),
+        };
+
+        let spawn = parse_spawn(value).map_err(|e| format!("{}: {}", line_no + 1, e))?;
+        legend.push((symbol, spawn));
+    }
+
+    let mut cells = vec![];
+
+    for (line_no, line) in lines {
+        let row = line
+            .chars()
+            .enumerate()
+            .map(|(column, symbol)| match symbol {
+                ' ' => Ok(Cell::Untouched),
+                '#' => Ok(Cell::Wall),
+                '.' => Ok(Cell::Floor),
+                '+' => Ok(Cell::Door),
+                '~' => Ok(Cell::Water),
+                _ => legend
+                    .iter()
+                    .find(|&&(s, _)| s == symbol)
+                    .map(|&(_, spawn)| Cell::Spawn(spawn))
+                    .ok_or_else(|| {
+                        format!("{}:{}: `{}` is not in the legend", line_no + 1, column + 1, symbol)
+                    }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        cells.push(row);
+    }
+
+    while cells.last().map(Vec::is_empty) == Some(true) {
+        cells.pop();
+    }
+
+    if cells.is_empty() {
+        return Err("1: vault has no map".to_string());
+    }
+
+    Ok(Vault {
+        name: name.unwrap_or_else(|| "vault".to_string()),
+        cells,
+    })
+}
+
+fn parse_spawn(value: &str) -> Result<Spawn, String> {
+    let mut words = value.split_whitespace();
+
+    let spawn = match (words.next(), words.next(), words.next()) {
+        (Some("monster"), Some(id), None) => monsters::get(id)
+            .map(Spawn::Monster)
+            .ok_or_else(|| format!("unknown monster `{}`", id))?,
+        (Some("item"), Some(id), None) => items::get(id)
+            .map(Spawn::Item)
+            .ok_or_else(|| format!("unknown item `{}`", id))?,
+        _ => return Err(format!("expected `monster <name>` or `item <name>`, got `{}`", value)),
+    };
+
+    Ok(spawn)
+}
+
+/// Try to stamp `vault` somewhere on the generated map. A spot only counts
+/// if the vault stays inside the outer wall, doesn't cover the start or the
+/// stairs, and every floor tile is still reachable afterwards.
+pub fn place_vault(
+    generated: &mut GeneratedMap,
+    vault: &Vault,
+    rng: &mut GameRng,
+) -> Option<Vec<(i32, i32, Spawn)>> {
+    let (width, height) = (vault.width(), vault.height());
+
+    if width + 2 > MAP_WIDTH || height + 2 > MAP_HEIGHT {
+        return None;
+    }
+
+    for _ in 0..VAULT_PLACEMENT_ATTEMPTS {
+        let x = rng.gen_range(1, MAP_WIDTH - width);
+        let y = rng.gen_range(1, MAP_HEIGHT - height);
+
+        let covers_exits = vault
+            .cells_at(x, y)
+            .any(|(cx, cy, _)| (cx, cy) == generated.start || (cx, cy) == generated.stairs);
+
+        if covers_exits {
+            continue;
+        }
+
+        let mut map = generated.map.clone();
+        let mut spawns = vec![];
+
+        for (cx, cy, cell) in vault.cells_at(x, y) {
+            map[cx as usize][cy as usize] = match cell {
+                Cell::Wall => Tile::wall(),
+                Cell::Door => Tile::new(TileKind::ClosedDoor),
+                Cell::Water => Tile::new(TileKind::ShallowWater),
+                Cell::Spawn(spawn) => {
+                    spawns.push((cx, cy, spawn));
+                    Tile::empty()
+                }
+                _ => Tile::empty(),
+            };
+        }
+
+        if floor_regions(&map).len() != 1 {
+            continue;
+        }
+
+        generated.map = map;
+        generated.prune_regions();
+
+        return Some(spawns);
+    }
+
+    None
+}